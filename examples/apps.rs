@@ -22,9 +22,9 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let is_simulator = matches.is_present("is-simulator");
 
     let transport_type = if is_simulator {
-        iota_ledger_nano::TransportTypes::TCP
+        iota_ledger_nano::TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        iota_ledger_nano::TransportTypes::NativeHID
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
 
     let (app, version) = iota_ledger_nano::get_opened_app(&transport_type)?;
@@ -635,7 +635,12 @@ pub fn random_essence(
 
     println!();
     // sign
-    let signature_bytes = ledger.sign(num_inputs).expect("error signing");
+    let signature_bytes: Vec<u8> = ledger
+        .sign(num_inputs)
+        .expect("error signing")
+        .iter()
+        .flat_map(|unlock| unlock.to_bytes())
+        .collect();
     println!("signature: {}", hex(&signature_bytes));
     println!();
 
@@ -815,9 +820,9 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     };
 
     let transport_type = if is_simulator {
-        iota_ledger_nano::TransportTypes::TCP
+        iota_ledger_nano::TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        iota_ledger_nano::TransportTypes::NativeHID
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
 
     if matches.is_present("recorder") {
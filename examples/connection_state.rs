@@ -75,9 +75,9 @@ pub fn get_ledger_nano_status(is_simulator: bool) -> LedgerNanoStatus {
     log::debug!("get_ledger_nano_status");
     // lock the mutex
     let transport_type = if is_simulator {
-        TransportTypes::TCP
+        TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        TransportTypes::NativeHID
+        TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
 
     log::debug!("get_opened_app");
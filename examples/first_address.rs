@@ -1,4 +1,3 @@
-use bech32::{self, ToBase32};
 use clap::{App, Arg};
 
 use std::error::Error;
@@ -37,22 +36,18 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let is_simulator = matches.is_present("is-simulator");
 
     let transport_type = if is_simulator {
-        iota_ledger_nano::TransportTypes::TCP
+        iota_ledger_nano::TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        iota_ledger_nano::TransportTypes::NativeHID
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
-    let hrp;
-    let chain;
-
-    (hrp, chain) = match matches.value_of("coin-type") {
+    let chain = match matches.value_of("coin-type") {
         Some(c) => match c {
-            "iota" => ("iota", 0x107a),
-            "smr" => ("smr", 0x107b),
-            "rms" => ("rms", 0x1),
-            "atoi" => ("atoi", 0x1),
+            "iota" => 0x107a,
+            "smr" => 0x107b,
+            "rms" | "atoi" => 0x1,
             _ => panic!("unknown coin type"),
         },
-        None => ("iota", 0x107a),
+        None => 0x107a,
     };
 
     let ledger = iota_ledger_nano::get_ledger_by_type(chain, BIP32_ACCOUNT, &transport_type, None)?;
@@ -62,20 +57,13 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         bip32_index: BIP32_INDEX,
     };
 
-    // generate address without prompt
-    let addresses = ledger.get_addresses(false, bip32_indices, 1)?;
-    let address_bytes = match addresses.first() {
+    // generate address without prompt, the HRP depends on the app mode
+    let addresses = ledger.get_bech32_addresses(false, bip32_indices, 1)?;
+    let bech32_address = match addresses.first() {
         Some(a) => a,
         None => panic!("no address was generated!"),
     };
 
-    let mut addr_bytes_with_type = [0u8; 33];
-
-    // first address byte is 0 for ed25519
-    addr_bytes_with_type[1..33].clone_from_slice(&address_bytes[..]);
-
-    let bech32_address = bech32::encode(hrp, addr_bytes_with_type.to_base32()).unwrap();
-
     println!(
         "first address (2c'/{:x}'/{:x}'/{:x}'/{:x}'): {}",
         chain,
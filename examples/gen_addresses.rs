@@ -1,4 +1,3 @@
-use bech32::{self, ToBase32};
 use clap::{App, Arg};
 
 use std::error::Error;
@@ -31,20 +30,17 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         )
         .get_matches();
 
-    let transport_type = iota_ledger_nano::TransportTypes::NativeHID;
+    let transport_type =
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First);
 
-    let hrp;
-    let chain;
-
-    (hrp, chain) = match matches.value_of("coin-type") {
+    let chain = match matches.value_of("coin-type") {
         Some(c) => match c {
-            "iota" => ("iota", 0x107a),
-            "smr" => ("smr", 0x107b),
-            "rms" => ("rms", 0x1),
-            "atoi" => ("atoi", 0x1),
+            "iota" => 0x107a,
+            "smr" => 0x107b,
+            "rms" | "atoi" => 0x1,
             _ => panic!("unknown coin type"),
         },
-        None => ("smr", 0x107b),
+        None => 0x107b,
     };
 
     let count = match matches.value_of("number") {
@@ -63,19 +59,13 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         };
 
         // generate address without prompt
-        let addresses = ledger.get_addresses(false, bip32_indices, 1)?;
-        let address_bytes = match addresses.first() {
+        // the HRP is the one of the app mode
+        let addresses = ledger.get_bech32_addresses(false, bip32_indices, 1)?;
+        let bech32_address = match addresses.first() {
             Some(a) => a,
             None => panic!("no address was generated!"),
         };
 
-        let mut addr_bytes_with_type = [0u8; 33];
-
-        // first address byte is 0 for ed25519
-        addr_bytes_with_type[1..33].clone_from_slice(&address_bytes[..]);
-
-        let bech32_address = bech32::encode(hrp, addr_bytes_with_type.to_base32()).unwrap();
-
         println!(
             "wallet address (2c'/{:x}'/{:x}'/{:x}'/{:x}'): {}",
             chain,
@@ -1,35 +1,26 @@
-use bech32::{self, ToBase32};
 use clap::{App, Arg};
 
 use std::error::Error;
 
 use blake2::digest::{Update, VariableOutput};
 use blake2::VarBlake2b;
-use iota_ledger_nano::LedgerBIP32Index;
+use iota_ledger_nano::{address, LedgerBIP32Index};
 
 const HARDENED: u32 = 0x80000000;
 
 const BIP32_CHANGE: u32 = /*0 |*/ HARDENED;
 const BIP32_INDEX: u32 = /*0 |*/ HARDENED;
 
-pub fn get_addr_from_pubkey(pubkey: [u8; 32]) -> [u8; 33] {
+pub fn get_addr_from_pubkey(pubkey: [u8; 32]) -> [u8; 32] {
     let mut hasher = VarBlake2b::new(32).unwrap();
     hasher.update(pubkey);
-    let mut result: [u8; 33] = [0; 33];
+    let mut result: [u8; 32] = [0; 32];
     hasher.finalize_variable(|res| {
-        result[1..33].clone_from_slice(&res[..32]);
+        result.clone_from_slice(&res[..32]);
     });
     result
 }
 
-pub fn bech32_from_public_key(hrp: &str, public_key: [u8; 32]) -> String {
-    bech32_from_address(hrp, get_addr_from_pubkey(public_key))
-}
-
-pub fn bech32_from_address(hrp: &str, address_bytes: [u8; 33]) -> String {
-    bech32::encode(hrp, address_bytes.to_base32()).unwrap()
-}
-
 pub fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
@@ -61,23 +52,19 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let is_simulator = matches.is_present("is-simulator");
 
     let transport_type = if is_simulator {
-        iota_ledger_nano::TransportTypes::TCP
+        iota_ledger_nano::TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        iota_ledger_nano::TransportTypes::NativeHID
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
 
-    let hrp;
-    let chain;
-
-    (hrp, chain) = match matches.value_of("coin-type") {
+    let chain = match matches.value_of("coin-type") {
         Some(c) => match c {
-            "iota" => ("iota", 0x107a),
-            "smr" => ("smr", 0x107b),
-            "rms" => ("rms", 0x1),
-            "atoi" => ("atoi", 0x1),
+            "iota" => 0x107a,
+            "smr" => 0x107b,
+            "rms" | "atoi" => 0x1,
             _ => panic!("unknown coin type"),
         },
-        None => ("smr", 0x107b),
+        None => 0x107b,
     };
 
     let count = match matches.value_of("number") {
@@ -96,8 +83,10 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         };
 
         // generate address without prompt
-        let addresses = ledger.get_addresses(false, bip32_indices, 1)?;
-        let address_bytes = match addresses.first() {
+        // the HRP is the one of the app mode
+        let hrp = ledger.hrp().expect("account is set");
+        let addresses = ledger.get_bech32_addresses(false, bip32_indices, 1)?;
+        let bech32_address = match addresses.first() {
             Some(a) => a,
             None => panic!("no address was generated!"),
         };
@@ -113,15 +102,10 @@ pub fn main() -> Result<(), Box<dyn Error>> {
             .map(|b| format!("{:02x}", b))
             .collect();
 
-        let mut addr_bytes_with_type = [0u8; 33];
-
-        // first address byte is 0 for ed25519
-        addr_bytes_with_type[1..33].clone_from_slice(&address_bytes[..]);
-
-        let bech32_address = bech32_from_address(hrp, addr_bytes_with_type);
-        let bech32_address_from_pubkey = bech32_from_public_key(hrp, *public_key_bytes);
+        let bech32_address_from_pubkey =
+            address::to_bech32(hrp, &get_addr_from_pubkey(*public_key_bytes))?;
 
-        if bech32_address != bech32_address_from_pubkey {
+        if *bech32_address != bech32_address_from_pubkey {
             panic!(
                 "validation failed! {} vs {}",
                 bech32_address, bech32_address_from_pubkey
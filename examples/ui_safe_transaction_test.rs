@@ -107,7 +107,12 @@ pub fn random_essence(
     ledger.user_confirm().expect("error user confirm");
 
     // sign
-    let signature_bytes = ledger.sign(num_inputs).expect("error signing");
+    let signature_bytes: Vec<u8> = ledger
+        .sign(num_inputs)
+        .expect("error signing")
+        .iter()
+        .flat_map(|unlock| unlock.to_bytes())
+        .collect();
 
     println!();
     println!("signature: {}", hex(&signature_bytes));
@@ -173,9 +178,9 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let is_simulator = matches.is_present("is-simulator");
 
     let transport_type = if is_simulator {
-        iota_ledger_nano::TransportTypes::TCP
+        iota_ledger_nano::TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        iota_ledger_nano::TransportTypes::NativeHID
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
 
     let chain = match matches.value_of("coin-type") {
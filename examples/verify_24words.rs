@@ -1,4 +1,3 @@
-use bech32::{self, ToBase32};
 use clap::{App, Arg};
 
 use std::error::Error;
@@ -7,7 +6,7 @@ use std::str::FromStr;
 use blake2::digest::{Update, VariableOutput};
 use blake2::VarBlake2b;
 
-use iota_ledger_nano::LedgerBIP32Index;
+use iota_ledger_nano::{address, LedgerBIP32Index};
 
 use bip39::Mnemonic;
 
@@ -73,14 +72,6 @@ pub fn get_addr(
     Ok(get_addr_from_pubkey(truncated))
 }
 
-/// get address as bech32 string
-pub fn get_bech32_address(hrp: &str, address_bytes: [u8; 32]) -> String {
-    let mut addr_bytes_with_type = [0u8; 33];
-    // first address byte is 0 for ed25519
-    addr_bytes_with_type[1..33].clone_from_slice(&address_bytes[..]);
-    bech32::encode(hrp, addr_bytes_with_type.to_base32()).unwrap()
-}
-
 fn trim_newline(s: &mut String) {
     if s.ends_with('\n') {
         s.pop();
@@ -126,18 +117,20 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let is_simulator = matches.is_present("is-simulator");
 
     let transport_type = if is_simulator {
-        iota_ledger_nano::TransportTypes::TCP
+        iota_ledger_nano::TransportTypes::TCP(iota_ledger_nano::TCPConfig::from_env())
     } else {
-        iota_ledger_nano::TransportTypes::NativeHID
+        iota_ledger_nano::TransportTypes::NativeHID(iota_ledger_nano::HIDSelector::First)
     };
 
     let ledger =
         iota_ledger_nano::get_ledger_by_type(0x107a, BIP32_ACCOUNT, &transport_type, None)?;
 
-    let (hrp, chain) = match !ledger.is_debug_app() {
-        true => ("iota", 0x107a),
-        false => ("atoi", 0x1),
+    let chain = match !ledger.is_debug_app() {
+        true => 0x107a,
+        false => 0x1,
     };
+    // the HRP is the one of the app mode
+    let hrp = ledger.hrp().expect("account is set");
 
     let bip32_indices = LedgerBIP32Index {
         bip32_change: BIP32_CHANGE,
@@ -145,14 +138,12 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     };
 
     // generate address without prompt
-    let addresses = ledger.get_addresses(false, bip32_indices, 1)?;
-    let address_bytes = match addresses.first() {
+    let addresses = ledger.get_bech32_addresses(false, bip32_indices, 1)?;
+    let bech32_ledger_address = match addresses.first() {
         Some(a) => a,
         None => panic!("no address was generated!"),
     };
 
-    let bech32_ledger_address = get_bech32_address(hrp, *address_bytes);
-
     println!();
     println!(
         "ledger-address     (2c'/{:x}'/{:x}'/{:x}'/{:x}'): {}",
@@ -181,7 +172,7 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     let seed = get_seed(words.as_str(), &password);
 
     let address_bytes = get_addr(&seed, chain, BIP32_ACCOUNT, bip32_indices).unwrap();
-    let bech32_address = get_bech32_address(hrp, address_bytes);
+    let bech32_address = address::to_bech32(hrp, &address_bytes)?;
 
     println!(
         "calculated-address (2c'/{:x}'/{:x}'/{:x}'/{:x}'): {}",
@@ -193,7 +184,7 @@ pub fn main() -> Result<(), Box<dyn Error>> {
     );
 
     println!();
-    if bech32_ledger_address != bech32_address {
+    if *bech32_ledger_address != bech32_address {
         println!();
         println!("addresses DON'T match!");
     } else {
@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LedgerAddressError {
+    /// invalid characters, mixed case, wrong checksum, ...
+    #[error("Address: invalid bech32: {0}")]
+    Bech32(#[from] bech32::Error),
+    /// only Ed25519 addresses are generated by the app
+    #[error("Address: unsupported address type {0}")]
+    UnsupportedAddressType(u8),
+    /// decoded address doesn't have the length of an Ed25519 address
+    #[error("Address: invalid length {0}")]
+    InvalidLength(usize),
+    /// address belongs to another network
+    #[error("Address: expected HRP {expected} but got {got}")]
+    UnexpectedHrp { expected: String, got: String },
+}
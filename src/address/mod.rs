@@ -0,0 +1,122 @@
+//! Bech32 representation of the Ed25519 addresses generated by the app
+//!
+//! The HRP depends on the app mode (see [AppModes::hrp](crate::api::constants::AppModes::hrp)):
+//! `iota`, `atoi` (IOTA testnet), `smr` and `rms` (Shimmer testnet).
+
+mod errors;
+
+use bech32::{FromBase32, ToBase32};
+
+pub use errors::LedgerAddressError;
+
+use crate::api::constants::{ADDRESS_SIZE_BYTES, ADDRESS_WITH_TYPE_SIZE_BYTES};
+
+pub const ED25519_ADDRESS_TYPE: u8 = 0;
+
+/// Encodes an Ed25519 address (without type byte)
+pub fn to_bech32(
+    hrp: &str,
+    address: &[u8; ADDRESS_SIZE_BYTES],
+) -> Result<String, LedgerAddressError> {
+    let mut address_with_type = [0u8; ADDRESS_WITH_TYPE_SIZE_BYTES];
+    address_with_type[0] = ED25519_ADDRESS_TYPE;
+    address_with_type[1..].copy_from_slice(address);
+    Ok(bech32::encode(hrp, address_with_type.to_base32())?)
+}
+
+/// Decodes a bech32 Ed25519 address, returns the HRP and the address without type byte
+///
+/// Fails if the checksum is wrong or it's not an Ed25519 address.
+pub fn from_bech32(
+    address: &str,
+) -> Result<(String, [u8; ADDRESS_SIZE_BYTES]), LedgerAddressError> {
+    let (hrp, data) = bech32::decode(address)?;
+    let bytes = Vec::<u8>::from_base32(&data)?;
+    if bytes.len() != ADDRESS_WITH_TYPE_SIZE_BYTES {
+        return Err(LedgerAddressError::InvalidLength(bytes.len()));
+    }
+    if bytes[0] != ED25519_ADDRESS_TYPE {
+        return Err(LedgerAddressError::UnsupportedAddressType(bytes[0]));
+    }
+    let mut ed25519 = [0u8; ADDRESS_SIZE_BYTES];
+    ed25519.copy_from_slice(&bytes[1..]);
+    Ok((hrp, ed25519))
+}
+
+/// Like [from_bech32] but also checks the HRP
+pub fn from_bech32_with_hrp(
+    address: &str,
+    expected_hrp: &str,
+) -> Result<[u8; ADDRESS_SIZE_BYTES], LedgerAddressError> {
+    let (hrp, ed25519) = from_bech32(address)?;
+    if hrp != expected_hrp {
+        return Err(LedgerAddressError::UnexpectedHrp {
+            expected: String::from(expected_hrp),
+            got: hrp,
+        });
+    }
+    Ok(ed25519)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: [u8; ADDRESS_SIZE_BYTES] = [
+        0x52, 0xfd, 0xfc, 0x07, 0x21, 0x82, 0x65, 0x4f, 0x16, 0x3f, 0x5f, 0x0f, 0x9a, 0x62, 0x1d,
+        0x72, 0x95, 0x66, 0xc7, 0x4d, 0x10, 0x03, 0x7c, 0x4d, 0x7b, 0xbb, 0x04, 0x07, 0xd1, 0xe2,
+        0xc6, 0x49,
+    ];
+
+    #[test]
+    fn round_trip() {
+        for hrp in ["iota", "atoi", "smr", "rms"] {
+            let bech32 = to_bech32(hrp, &ADDRESS).unwrap();
+            assert!(bech32.starts_with(&format!("{}1q", hrp)));
+            assert_eq!(from_bech32(&bech32).unwrap(), (String::from(hrp), ADDRESS));
+            assert_eq!(from_bech32_with_hrp(&bech32, hrp).unwrap(), ADDRESS);
+        }
+    }
+
+    #[test]
+    fn known_address() {
+        let bech32 = "iota1qpf0mlq8yxpx2nck8a0slxnzr4ef2ek8f5gqxlzd0wasgp73utryj430ldu";
+        assert_eq!(to_bech32("iota", &ADDRESS).unwrap(), bech32);
+    }
+
+    #[test]
+    fn invalid() {
+        let bech32 = to_bech32("smr", &ADDRESS).unwrap();
+        assert_eq!(
+            from_bech32_with_hrp(&bech32, "iota"),
+            Err(LedgerAddressError::UnexpectedHrp {
+                expected: String::from("iota"),
+                got: String::from("smr"),
+            })
+        );
+
+        // last character changed, checksum is wrong
+        let mut tampered = bech32.clone();
+        let last = tampered.pop().unwrap();
+        tampered.push(if last == 'q' { 'p' } else { 'q' });
+        assert!(matches!(
+            from_bech32(&tampered),
+            Err(LedgerAddressError::Bech32(_))
+        ));
+
+        // alias address (type 8)
+        let mut alias = [8u8; ADDRESS_WITH_TYPE_SIZE_BYTES];
+        alias[1..].copy_from_slice(&ADDRESS);
+        let bech32 = bech32::encode("smr", alias.to_base32()).unwrap();
+        assert_eq!(
+            from_bech32(&bech32),
+            Err(LedgerAddressError::UnsupportedAddressType(8))
+        );
+
+        let bech32 = bech32::encode("smr", ADDRESS.to_base32()).unwrap();
+        assert_eq!(
+            from_bech32(&bech32),
+            Err(LedgerAddressError::InvalidLength(ADDRESS_SIZE_BYTES))
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 
-pub fn exec(transport: &Transport) -> Result<(), errors::APIError> {
+pub fn exec<T: Exchange>(transport: &T) -> Result<(), errors::APIError> {
     let cmd = APDUCommand {
         cla: constants::APDUCLASSB0,
         ins: constants::APDUInstructionsBolos::AppExitB0 as u8,
@@ -11,5 +11,5 @@ pub fn exec(transport: &Transport) -> Result<(), errors::APIError> {
         p2: 0,
         data: Vec::new(),
     };
-    helpers::exec::<()>(transport, cmd)
+    helpers::exec::<_, ()>(transport, cmd)
 }
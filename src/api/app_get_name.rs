@@ -1,7 +1,7 @@
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 /*
@@ -77,7 +77,7 @@ impl Response {
     // NOP
 }
 
-pub fn exec(transport: &Transport) -> Result<Response, errors::APIError> {
+pub fn exec<T: Exchange>(transport: &T) -> Result<Response, errors::APIError> {
     let cmd = APDUCommand {
         cla: constants::APDUCLASSB0,
         ins: constants::APDUInstructionsBolos::GetAppVersionB0 as u8,
@@ -85,5 +85,5 @@ pub fn exec(transport: &Transport) -> Result<Response, errors::APIError> {
         p2: 0,
         data: Vec::new(),
     };
-    helpers::exec::<Response>(transport, cmd)
+    helpers::exec::<_, Response>(transport, cmd)
 }
@@ -1,7 +1,7 @@
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 /*
@@ -34,11 +34,11 @@ impl Packable for Request {
     }
 }
 
-pub fn exec(transport: &Transport, app: String) -> Result<(), errors::APIError> {
+pub fn exec<T: Exchange>(transport: &T, app: String) -> Result<(), errors::APIError> {
     let req = Request { app };
 
     let mut buf = Vec::new();
-    let _ = req.pack(&mut buf);
+    req.pack(&mut buf)?;
 
     // string serializer stores a length byte that is unwanted here because
     // the p3 parameter will be the length of the string and the data itself
@@ -52,5 +52,39 @@ pub fn exec(transport: &Transport, app: String) -> Result<(), errors::APIError>
         p2: 0,
         data: buf,
     };
-    helpers::exec::<()>(transport, cmd)
+    helpers::exec::<_, ()>(transport, cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ledger::ledger_transport_mock::TransportMock;
+
+    #[test]
+    fn command() {
+        let mock = TransportMock::new();
+        mock.expect_ok(
+            APDUCommand {
+                cla: constants::APDUCLASSE0,
+                ins: constants::APDUInstructionsBolos::OpenAppE0 as u8,
+                p1: 0,
+                p2: 0,
+                data: b"IOTA".to_vec(),
+            },
+            &[],
+        );
+        exec(&mock, "IOTA".to_string()).unwrap();
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn name_too_long() {
+        let mock = TransportMock::new();
+        assert!(matches!(
+            exec(&mock, "x".repeat(256)),
+            Err(errors::APIError::Packable(PackableError::StringTooLong))
+        ));
+        mock.verify().unwrap();
+    }
 }
@@ -7,6 +7,7 @@ pub const APDUCLASS: u8 = 0x7b;
 pub const ADDRESS_WITH_TYPE_SIZE_BYTES: usize = 33;
 pub const ADDRESS_SIZE_BYTES: usize = 32;
 pub const PUBLIC_KEY_SIZE_BYTES: usize = 32;
+pub const ESSENCE_HASH_SIZE_BYTES: usize = 32;
 
 pub enum APDUInstructions {
     None = 0x00,
@@ -54,6 +55,7 @@ pub enum Apps {
     AppShimmer = 1,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AppModes {
     ModeIOTAStardust = 0x01,
     ModeIOTAStardustTestnet = 0x81,
@@ -62,6 +64,20 @@ pub enum AppModes {
     ModeShimmer = 0x03,
     ModeShimmerTestnet = 0x83,
 }
+
+impl AppModes {
+    /// Bech32 HRP of the addresses of the network the mode signs for
+    ///
+    /// Claiming modes use IOTA keys (coin type 0x107a) but sign for Shimmer.
+    pub fn hrp(&self) -> &'static str {
+        match self {
+            AppModes::ModeIOTAStardust => "iota",
+            AppModes::ModeIOTAStardustTestnet => "atoi",
+            AppModes::ModeShimmerClaiming | AppModes::ModeShimmer => "smr",
+            AppModes::ModeShimmerClaimingTestnet | AppModes::ModeShimmerTestnet => "rms",
+        }
+    }
+}
 #[derive(Debug, Copy, Clone)]
 pub enum DataTypeEnum {
     Empty = 0,
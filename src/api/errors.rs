@@ -1,5 +1,13 @@
 use thiserror::Error;
 
+#[cfg(feature = "speculos-http")]
+use crate::transport::errors::LedgerHTTPError;
+use crate::transport::errors::{LedgerHIDError, LedgerMockError, LedgerTCPError};
+
+/// Errors of the API
+///
+/// Status words of the app, the OS and the dashboard have their own variants, unknown ones end
+/// up in [APIError::UnknownStatus]. [APIError::retcode] gives the raw status word back.
 #[derive(Error, Debug)]
 pub enum APIError {
     #[error("No error")]
@@ -35,9 +43,79 @@ pub enum APIError {
     #[error("Command timeout")]
     CommandTimeout,
 
+    // status words of the OS and the dashboard
+    #[error("Device locked")]
+    DeviceLocked, // 0x5515
+
+    #[error("Refused by user")]
+    UserRefused, // 0x5501, e.g. opening an app
+
+    #[error("PIN not set")]
+    PinNotSet, // 0x5502
+
+    #[error("Not enough space")]
+    NotEnoughSpace, // 0x5102
+
+    #[error("App not installed")]
+    AppNotInstalled(u16), // 0x6807, 0x6984
+
+    #[error("App not open")]
+    AppNotOpen(u16), // 0x6e01, 0x6511, 0x6d02 (depends on firmware)
+
+    #[error("Invalid app name length")]
+    InvalidAppNameLength, // 0x670a
+
+    #[error("Device not onboarded")]
+    DeviceNotOnboarded(u16), // 0x6d07, 0x6611
+
+    #[error("Wrong PIN ({0} attempts remaining)")]
+    PinRemainingAttempts(u8), // 0x63cX
+
+    #[error("Missing critical parameter")]
+    MissingCriticalParameter, // 0x6800
+
+    #[error("Incompatible file structure")]
+    IncompatibleFileStructure, // 0x6981
+
+    #[error("Not enough memory space")]
+    NotEnoughMemorySpace, // 0x6a84
+
+    #[error("Referenced data not found")]
+    ReferencedDataNotFound, // 0x6a88
+
+    #[error("File already exists")]
+    FileAlreadyExists, // 0x6a89
+
+    #[error("Technical problem")]
+    TechnicalProblem, // 0x6f00
+
+    #[error("Device halted")]
+    Halted, // 0x6faa
+
+    #[error("Unknown status word {0:#06x}")]
+    UnknownStatus(u16),
+
+    /// transport failed without further details
     #[error("Transport error")]
     TransportError,
 
+    #[error(transparent)]
+    HID(LedgerHIDError),
+
+    #[error(transparent)]
+    TCP(LedgerTCPError),
+
+    #[cfg(feature = "speculos-http")]
+    #[error(transparent)]
+    HTTP(LedgerHTTPError),
+
+    #[error(transparent)]
+    Mock(LedgerMockError),
+
+    /// error of a caller provided [Exchange](crate::transport::Exchange) implementation
+    #[error(transparent)]
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+
     #[error("Essence too large")]
     EssenceTooLarge,
 
@@ -47,11 +125,75 @@ pub enum APIError {
     #[error("Timeout")]
     Timeout,
 
+    #[error("Transport is in use")]
+    TransportBusy,
+
+    #[error("Exchange cancelled")]
+    Cancelled,
+
+    /// background task of the async API was cancelled (runtime shut down)
+    #[error("Async task cancelled")]
+    TaskCancelled,
+
+    /// background task of the async API panicked
+    #[error("Async task panicked: {0}")]
+    TaskPanicked(String),
+
+    #[error("APDU command too long")]
+    CommandTooLong,
+
+    /// `set_account` wasn't called, so the network of the addresses is unknown
+    #[error("Account not set")]
+    AccountNotSet,
+
+    #[error(transparent)]
+    Address(#[from] crate::address::LedgerAddressError),
+
+    /// essence rejected by [validate_essence](crate::validate::validate_essence)
+    #[error(transparent)]
+    Essence(#[from] crate::validate::LedgerEssenceError),
+
+    /// a command couldn't be serialized, e.g. an app name over 255 bytes
+    #[error(transparent)]
+    Packable(#[from] crate::api::packable::Error),
+
     #[error("unknown")]
     Unknown,
 }
 
 impl APIError {
+    /// Converts the error of an [Exchange](crate::transport::Exchange) implementation
+    ///
+    /// Errors of the transports of this crate end up in their own variant, everything else
+    /// in [APIError::Transport].
+    pub fn from_transport_error<E>(e: E) -> APIError
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let e: Box<dyn std::error::Error + Send + Sync> = Box::new(e);
+        let e = match e.downcast::<APIError>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<LedgerHIDError>() {
+            Ok(e) => return APIError::HID(*e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<LedgerTCPError>() {
+            Ok(e) => return APIError::TCP(*e),
+            Err(e) => e,
+        };
+        #[cfg(feature = "speculos-http")]
+        let e = match e.downcast::<LedgerHTTPError>() {
+            Ok(e) => return APIError::HTTP(*e),
+            Err(e) => e,
+        };
+        match e.downcast::<LedgerMockError>() {
+            Ok(e) => APIError::Mock(*e),
+            Err(e) => APIError::Transport(e),
+        }
+    }
+
     pub fn get_error(rc: u16) -> APIError {
         match rc {
             0x9000 => APIError::Ok,
@@ -65,7 +207,155 @@ impl APIError {
             0x6982 => APIError::SecurityStatusNotSatisfied,
             0x6985 => APIError::ConditionsOfUseNotSatisfied,
             0x6401 => APIError::CommandTimeout,
-            _ => APIError::Unknown,
+            0x5515 => APIError::DeviceLocked,
+            0x5501 => APIError::UserRefused,
+            0x5502 => APIError::PinNotSet,
+            0x5102 => APIError::NotEnoughSpace,
+            0x6807 | 0x6984 => APIError::AppNotInstalled(rc),
+            0x6e01 | 0x6511 | 0x6d02 => APIError::AppNotOpen(rc),
+            0x670a => APIError::InvalidAppNameLength,
+            0x6d07 | 0x6611 => APIError::DeviceNotOnboarded(rc),
+            0x63c0..=0x63cf => APIError::PinRemainingAttempts((rc & 0x0f) as u8),
+            0x6800 => APIError::MissingCriticalParameter,
+            0x6981 => APIError::IncompatibleFileStructure,
+            0x6a84 => APIError::NotEnoughMemorySpace,
+            0x6a88 => APIError::ReferencedDataNotFound,
+            0x6a89 => APIError::FileAlreadyExists,
+            0x6f00 => APIError::TechnicalProblem,
+            0x6faa => APIError::Halted,
+            _ => APIError::UnknownStatus(rc),
+        }
+    }
+
+    /// Status word the error was created from (see [APIError::get_error])
+    pub fn retcode(&self) -> Option<u16> {
+        let rc = match self {
+            APIError::Ok => 0x9000,
+            APIError::IncorrectLength => 0x6700,
+            APIError::CommandInvalidData => 0x6a80,
+            APIError::IncorrectP1P2 => 0x6b00,
+            APIError::IncorrectLengthP3 => 0x6c00,
+            APIError::InstructionNotSupported => 0x6d00,
+            APIError::ClassNotSupported => 0x6e00,
+            APIError::CommandNotAllowed => 0x6900,
+            APIError::SecurityStatusNotSatisfied => 0x6982,
+            APIError::ConditionsOfUseNotSatisfied => 0x6985,
+            APIError::CommandTimeout => 0x6401,
+            APIError::DeviceLocked => 0x5515,
+            APIError::UserRefused => 0x5501,
+            APIError::PinNotSet => 0x5502,
+            APIError::NotEnoughSpace => 0x5102,
+            APIError::AppNotInstalled(rc)
+            | APIError::AppNotOpen(rc)
+            | APIError::DeviceNotOnboarded(rc)
+            | APIError::UnknownStatus(rc) => *rc,
+            APIError::InvalidAppNameLength => 0x670a,
+            APIError::PinRemainingAttempts(attempts) => 0x63c0 | *attempts as u16,
+            APIError::MissingCriticalParameter => 0x6800,
+            APIError::IncompatibleFileStructure => 0x6981,
+            APIError::NotEnoughMemorySpace => 0x6a84,
+            APIError::ReferencedDataNotFound => 0x6a88,
+            APIError::FileAlreadyExists => 0x6a89,
+            APIError::TechnicalProblem => 0x6f00,
+            APIError::Halted => 0x6faa,
+            _ => return None,
+        };
+        Some(rc)
+    }
+
+    /// The user denied the action on the device (signing, opening an app, ...)
+    pub fn is_user_rejection(&self) -> bool {
+        matches!(
+            self,
+            APIError::ConditionsOfUseNotSatisfied | APIError::UserRefused
+        )
+    }
+
+    /// The device is locked and has to be unlocked with the PIN first
+    pub fn is_device_locked(&self) -> bool {
+        matches!(
+            self,
+            APIError::DeviceLocked | APIError::SecurityStatusNotSatisfied
+        )
+    }
+
+    /// The dashboard or another app is open instead of the expected app
+    pub fn is_app_not_open(&self) -> bool {
+        matches!(self, APIError::AppNotOpen(_) | APIError::ClassNotSupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_words() {
+        let table = [
+            (0x9000, APIError::Ok),
+            (0x6700, APIError::IncorrectLength),
+            (0x6a80, APIError::CommandInvalidData),
+            (0x6b00, APIError::IncorrectP1P2),
+            (0x6c00, APIError::IncorrectLengthP3),
+            (0x6d00, APIError::InstructionNotSupported),
+            (0x6e00, APIError::ClassNotSupported),
+            (0x6900, APIError::CommandNotAllowed),
+            (0x6982, APIError::SecurityStatusNotSatisfied),
+            (0x6985, APIError::ConditionsOfUseNotSatisfied),
+            (0x6401, APIError::CommandTimeout),
+            (0x5515, APIError::DeviceLocked),
+            (0x5501, APIError::UserRefused),
+            (0x5502, APIError::PinNotSet),
+            (0x5102, APIError::NotEnoughSpace),
+            (0x6807, APIError::AppNotInstalled(0x6807)),
+            (0x6984, APIError::AppNotInstalled(0x6984)),
+            (0x6e01, APIError::AppNotOpen(0x6e01)),
+            (0x6511, APIError::AppNotOpen(0x6511)),
+            (0x6d02, APIError::AppNotOpen(0x6d02)),
+            (0x670a, APIError::InvalidAppNameLength),
+            (0x6d07, APIError::DeviceNotOnboarded(0x6d07)),
+            (0x6611, APIError::DeviceNotOnboarded(0x6611)),
+            (0x63c0, APIError::PinRemainingAttempts(0)),
+            (0x63c3, APIError::PinRemainingAttempts(3)),
+            (0x63cf, APIError::PinRemainingAttempts(15)),
+            (0x6800, APIError::MissingCriticalParameter),
+            (0x6981, APIError::IncompatibleFileStructure),
+            (0x6a84, APIError::NotEnoughMemorySpace),
+            (0x6a88, APIError::ReferencedDataNotFound),
+            (0x6a89, APIError::FileAlreadyExists),
+            (0x6f00, APIError::TechnicalProblem),
+            (0x6faa, APIError::Halted),
+            (0x1234, APIError::UnknownStatus(0x1234)),
+        ];
+
+        for (sw, expected) in table {
+            let error = APIError::get_error(sw);
+            assert_eq!(
+                format!("{:?}", error),
+                format!("{:?}", expected),
+                "{:#06x}",
+                sw
+            );
+            assert_eq!(error.retcode(), Some(sw), "{:#06x}", sw);
         }
     }
+
+    #[test]
+    fn no_status_word() {
+        assert_eq!(APIError::Timeout.retcode(), None);
+        assert_eq!(APIError::CommandTooLong.retcode(), None);
+        assert_eq!(APIError::TransportError.retcode(), None);
+    }
+
+    #[test]
+    fn classification() {
+        assert!(APIError::get_error(0x6985).is_user_rejection());
+        assert!(APIError::get_error(0x5501).is_user_rejection());
+        assert!(APIError::get_error(0x6982).is_device_locked());
+        assert!(APIError::get_error(0x5515).is_device_locked());
+        assert!(APIError::get_error(0x6e00).is_app_not_open());
+        assert!(APIError::get_error(0x6e01).is_app_not_open());
+        assert!(!APIError::get_error(0x9000).is_user_rejection());
+        assert!(!APIError::get_error(0x6985).is_device_locked());
+    }
 }
@@ -1,7 +1,7 @@
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 
@@ -36,12 +36,7 @@ impl Packable for Request {
     }
 }
 
-pub fn exec(
-    transport: &Transport,
-    show: bool,
-    bip32: crate::LedgerBIP32Index,
-    count: u32,
-) -> Result<(), errors::APIError> {
+pub fn command(show: bool, bip32: crate::LedgerBIP32Index, count: u32) -> APDUCommand<Vec<u8>> {
     let req = Request {
         bip32_index: bip32.bip32_index,
         bip32_change: bip32.bip32_change,
@@ -51,12 +46,20 @@ pub fn exec(
     let mut buf = Vec::new();
     let _ = req.pack(&mut buf);
 
-    let cmd = APDUCommand {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::GeneratePublicKeys as u8,
         p1: if show { 1 } else { 0 },
         p2: 0u8,
         data: buf,
-    };
-    helpers::exec::<()>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(
+    transport: &T,
+    show: bool,
+    bip32: crate::LedgerBIP32Index,
+    count: u32,
+) -> Result<(), errors::APIError> {
+    helpers::exec::<_, ()>(transport, command(show, bip32, count))
 }
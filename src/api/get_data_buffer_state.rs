@@ -1,7 +1,7 @@
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::constants::DataTypeEnum;
 use crate::api::{constants, errors, helpers};
@@ -48,13 +48,16 @@ impl Packable for Response {
     }
 }
 
-pub fn exec(transport: &Transport) -> Result<Response, errors::APIError> {
-    let cmd = APDUCommand {
+pub fn command() -> APDUCommand<Vec<u8>> {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::GetDataBufferState as u8,
         p1: 0,
         p2: 0,
         data: Vec::new(),
-    };
-    helpers::exec::<Response>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(transport: &T) -> Result<Response, errors::APIError> {
+    helpers::exec::<_, Response>(transport, command())
 }
@@ -1,23 +1,57 @@
-use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use std::ops::Deref;
+
+use crate::ledger::ledger_apdu::{APDUAnswer, APDUCommand, APDUEncoding};
+use crate::transport::Exchange;
 
 use crate::api::{errors, packable};
 
-pub fn exec<T: packable::Packable>(
-    transport: &Transport,
+// nothing is sent if the payload doesn't fit into the command
+pub(crate) fn check_length(
+    cmd: &APDUCommand<Vec<u8>>,
+    encoding: APDUEncoding,
+) -> Result<(), errors::APIError> {
+    cmd.serialize_with(encoding).map(|_| ()).map_err(|e| {
+        log::error!("error: {}", e);
+        errors::APIError::CommandTooLong
+    })
+}
+
+pub(crate) fn parse_answer<B: Deref<Target = [u8]>, R: packable::Packable>(
+    resp: &APDUAnswer<B>,
+) -> Result<R, errors::APIError> {
+    if resp.retcode() != 0x9000 {
+        return Err(errors::APIError::get_error(resp.retcode()));
+    }
+    R::unpack(&mut &resp.data()[..]).map_err(|_| errors::APIError::Unknown)
+}
+
+pub fn exec<T: Exchange, R: packable::Packable>(
+    transport: &T,
     cmd: APDUCommand<Vec<u8>>,
-) -> Result<T, errors::APIError> {
-    match transport.transport.exchange(&cmd) {
-        Ok(resp) => {
-            if resp.retcode() != 0x9000 {
-                return Err(errors::APIError::get_error(resp.retcode()));
-            }
-            let res = T::unpack(&mut &resp.data()[..]).map_err(|_| errors::APIError::Unknown)?;
-            Ok(res)
+) -> Result<R, errors::APIError> {
+    check_length(&cmd, transport.apdu_encoding())?;
+
+    match transport.exchange(&cmd) {
+        Ok(resp) => parse_answer(&resp),
+        Err(e) => {
+            log::error!("error: {}", e);
+            Err(errors::APIError::from_transport_error(e))
         }
+    }
+}
+
+#[cfg(feature = "async")]
+pub async fn exec_async<T: crate::asynchronous::AsyncExchange, R: packable::Packable>(
+    transport: &T,
+    cmd: APDUCommand<Vec<u8>>,
+) -> Result<R, errors::APIError> {
+    check_length(&cmd, transport.apdu_encoding())?;
+
+    match transport.exchange(&cmd).await {
+        Ok(resp) => parse_answer(&resp),
         Err(e) => {
             log::error!("error: {}", e);
-            Err(errors::APIError::TransportError)
+            Err(errors::APIError::from_transport_error(e))
         }
     }
 }
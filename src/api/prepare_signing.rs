@@ -1,7 +1,7 @@
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 
@@ -36,12 +36,11 @@ impl Packable for Request {
     }
 }
 
-pub fn exec(
-    transport: &Transport,
+pub fn command(
     has_remainder: bool,
     remainder_index: u16,
     remainder: crate::LedgerBIP32Index,
-) -> Result<(), errors::APIError> {
+) -> APDUCommand<Vec<u8>> {
     let req = Request {
         remainder_index,
         remainder_bip32_index: remainder.bip32_index,
@@ -51,12 +50,23 @@ pub fn exec(
     let mut buf = Vec::new();
     let _ = req.pack(&mut buf);
 
-    let cmd = APDUCommand {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::PrepareSigning as u8,
         p1: 1, // compatibility
         p2: if has_remainder { 1 } else { 0 },
         data: buf,
-    };
-    helpers::exec::<()>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(
+    transport: &T,
+    has_remainder: bool,
+    remainder_index: u16,
+    remainder: crate::LedgerBIP32Index,
+) -> Result<(), errors::APIError> {
+    helpers::exec::<_, ()>(
+        transport,
+        command(has_remainder, remainder_index, remainder),
+    )
 }
@@ -1,7 +1,7 @@
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 
@@ -34,13 +34,16 @@ impl Packable for Response {
 
 impl Response {}
 
-pub fn exec(transport: &Transport, block_number: u8) -> Result<Response, errors::APIError> {
-    let cmd = APDUCommand {
+pub fn command(block_number: u8) -> APDUCommand<Vec<u8>> {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::ReadDataBlock as u8,
         p1: block_number,
         p2: 0,
         data: Vec::new(),
-    };
-    helpers::exec::<Response>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(transport: &T, block_number: u8) -> Result<Response, errors::APIError> {
+    helpers::exec::<_, Response>(transport, command(block_number))
 }
@@ -1,15 +1,18 @@
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 
-pub fn exec(transport: &Transport) -> Result<(), errors::APIError> {
-    let cmd = APDUCommand {
+pub fn command() -> APDUCommand<Vec<u8>> {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::Reset as u8,
         p1: 0,
         p2: 0,
         data: Vec::new(),
-    };
-    helpers::exec::<()>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(transport: &T) -> Result<(), errors::APIError> {
+    helpers::exec::<_, ()>(transport, command())
 }
@@ -1,5 +1,5 @@
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::get_app_config;
 use crate::api::{
@@ -37,21 +37,8 @@ impl Packable for Request {
     }
 }
 
-pub fn exec(
-    coin_type: u32,
-    app_config: get_app_config::Response,
-    transport: &Transport,
-    account: u32,
-) -> Result<(), errors::APIError> {
-    let req = Request {
-        bip32_account: account,
-    };
-
-    let mut buf = Vec::new();
-    let _ = req.pack(&mut buf);
-
-    let flags = get_app_config::AppConfigFlags::from(app_config.flags);
-
+/// Resolves the app mode from the coin type, the app and the account
+pub fn app_mode(coin_type: u32, app: Apps, account: u32) -> Result<AppModes, errors::APIError> {
     if ![0x1, 0x107a, 0x107b].contains(&coin_type) {
         return Err(errors::APIError::IncorrectP1P2);
     }
@@ -68,7 +55,7 @@ pub fn exec(
     // 0x03: (107b) Shimmer (default)
     // 0x83:    (1) Shimmer Testnet
 
-    let app_mode = match flags.app {
+    let app_mode = match app {
         Apps::AppIOTA => match coin_type {
             // IOTA + stardust
             0x107a => AppModes::ModeIOTAStardust,
@@ -93,13 +80,43 @@ pub fn exec(
             _ => return Err(errors::APIError::IncorrectP1P2),
         },
     };
+    Ok(app_mode)
+}
+
+pub fn command(app_mode: AppModes, account: u32) -> APDUCommand<Vec<u8>> {
+    let req = Request {
+        bip32_account: account,
+    };
+
+    let mut buf = Vec::new();
+    req.pack(&mut buf).expect("packing a u32 can't fail");
 
-    let cmd = APDUCommand {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::SetAccount as u8,
         p1: app_mode as u8,
         p2: 0,
         data: buf,
-    };
-    helpers::exec::<()>(transport, cmd)
+    }
+}
+
+/// App mode of the account for the app described by `app_config`
+pub fn app_mode_of(
+    coin_type: u32,
+    app_config: &get_app_config::Response,
+    account: u32,
+) -> Result<AppModes, errors::APIError> {
+    let flags = get_app_config::AppConfigFlags::from(app_config.flags);
+    app_mode(coin_type, flags.app, account)
+}
+
+pub fn exec<T: Exchange>(
+    coin_type: u32,
+    app_config: get_app_config::Response,
+    transport: &T,
+    account: u32,
+) -> Result<AppModes, errors::APIError> {
+    let app_mode = app_mode_of(coin_type, &app_config, account)?;
+    helpers::exec::<_, ()>(transport, command(app_mode, account))?;
+    Ok(app_mode)
 }
@@ -1,9 +1,12 @@
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::{constants, errors, helpers};
 
-pub fn exec(transport: &Transport, non_interactive_mode: bool) -> Result<(), errors::APIError> {
+pub fn exec<T: Exchange>(
+    transport: &T,
+    non_interactive_mode: bool,
+) -> Result<(), errors::APIError> {
     let cmd = APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::SetNonInteractiveMode as u8,
@@ -11,5 +14,5 @@ pub fn exec(transport: &Transport, non_interactive_mode: bool) -> Result<(), err
         p2: 0,
         data: Vec::new(),
     };
-    helpers::exec::<()>(transport, cmd)
+    helpers::exec::<_, ()>(transport, cmd)
 }
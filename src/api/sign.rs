@@ -1,10 +1,12 @@
 use crate::ledger::ledger_apdu::APDUCommand;
-use crate::Transport;
+use crate::transport::Exchange;
 
 use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
 use crate::api::{constants, errors, helpers};
 
+use std::convert::TryInto;
+
 const ED25519_PUBLIC_KEY_LENGTH: usize = 32;
 const ED25519_SIGNATURE_LENGTH: usize = 64;
 
@@ -59,13 +61,185 @@ impl Packable for ResponseVec {
     }
 }
 
-pub fn exec(transport: &Transport, signature_index: u8) -> Result<ResponseVec, errors::APIError> {
-    let cmd = APDUCommand {
+const SIGNATURE_UNLOCK_TYPE: u8 = 0;
+const REFERENCE_UNLOCK_TYPE: u8 = 1;
+const ED25519_SIGNATURE_TYPE: u8 = 0;
+
+/// Unlock of an input as returned by the device
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Unlock {
+    /// Ed25519 signature of the essence hash
+    Signature {
+        public_key: [u8; ED25519_PUBLIC_KEY_LENGTH],
+        signature: [u8; ED25519_SIGNATURE_LENGTH],
+    },
+    /// input is unlocked by the signature unlock at `index`
+    Reference { index: u16 },
+}
+
+impl Unlock {
+    /// Stardust wire format of the unlock
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.packed_len());
+        // writing to a Vec can't fail
+        let _ = self.pack(&mut buf);
+        buf
+    }
+}
+
+impl Packable for Unlock {
+    fn packed_len(&self) -> usize {
+        match self {
+            Unlock::Signature { .. } => SIGNATURE_UNLOCK_BLOCK_LENGTH,
+            Unlock::Reference { .. } => REFERENCE_UNLOCK_BLOCK_LENGTH,
+        }
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        match self {
+            Unlock::Signature {
+                public_key,
+                signature,
+            } => {
+                SIGNATURE_UNLOCK_TYPE.pack(buf)?;
+                ED25519_SIGNATURE_TYPE.pack(buf)?;
+                buf.write_all(public_key)?;
+                buf.write_all(signature)?;
+            }
+            Unlock::Reference { index } => {
+                REFERENCE_UNLOCK_TYPE.pack(buf)?;
+                index.pack(buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        match u8::unpack(buf)? {
+            SIGNATURE_UNLOCK_TYPE => {
+                if u8::unpack(buf)? != ED25519_SIGNATURE_TYPE {
+                    return Err(PackableError::InvalidType);
+                }
+                let mut public_key = [0u8; ED25519_PUBLIC_KEY_LENGTH];
+                buf.read_exact(&mut public_key)?;
+                let mut signature = [0u8; ED25519_SIGNATURE_LENGTH];
+                buf.read_exact(&mut signature)?;
+                Ok(Unlock::Signature {
+                    public_key,
+                    signature,
+                })
+            }
+            REFERENCE_UNLOCK_TYPE => Ok(Unlock::Reference {
+                index: u16::unpack(buf)?,
+            }),
+            _ => Err(PackableError::InvalidVariant),
+        }
+    }
+}
+
+/// Packs the unlocks of a transaction payload (count followed by the unlocks)
+pub fn pack_unlocks<W: Write>(unlocks: &[Unlock], buf: &mut W) -> Result<(), PackableError> {
+    let count: u16 = unlocks
+        .len()
+        .try_into()
+        .map_err(|_| PackableError::InvalidAnnouncedLen)?;
+    count.pack(buf)?;
+    for unlock in unlocks {
+        unlock.pack(buf)?;
+    }
+    Ok(())
+}
+
+pub fn command(signature_index: u8) -> APDUCommand<Vec<u8>> {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::SignSingle as u8,
         p1: signature_index,
         p2: 0,
         data: Vec::new(),
-    };
-    helpers::exec::<ResponseVec>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(
+    transport: &T,
+    signature_index: u8,
+) -> Result<ResponseVec, errors::APIError> {
+    helpers::exec::<_, ResponseVec>(transport, command(signature_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature() -> Unlock {
+        let mut public_key = [0u8; ED25519_PUBLIC_KEY_LENGTH];
+        public_key
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8);
+        Unlock::Signature {
+            public_key,
+            signature: [0xee; ED25519_SIGNATURE_LENGTH],
+        }
+    }
+
+    fn unpack(bytes: &[u8]) -> Result<Unlock, PackableError> {
+        Unlock::unpack(&mut &bytes[..])
+    }
+
+    #[test]
+    fn signature_layout() {
+        let bytes = signature().to_bytes();
+        assert_eq!(bytes.len(), 98);
+        assert_eq!(bytes.len(), signature().packed_len());
+        // signature unlock, Ed25519 signature
+        assert_eq!(&bytes[..2], &[0, 0]);
+        assert_eq!(bytes[2], 0);
+        assert_eq!(bytes[33], 31);
+        assert_eq!(&bytes[34..], &[0xee; 64]);
+        assert_eq!(unpack(&bytes).unwrap(), signature());
+    }
+
+    #[test]
+    fn reference_layout() {
+        let reference = Unlock::Reference { index: 0x0102 };
+        let bytes = reference.to_bytes();
+        assert_eq!(bytes, [1, 0x02, 0x01]);
+        assert_eq!(bytes.len(), reference.packed_len());
+        assert_eq!(unpack(&bytes).unwrap(), reference);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut bytes = signature().to_bytes();
+        bytes[1] = 1;
+        assert!(matches!(unpack(&bytes), Err(PackableError::InvalidType)));
+        assert!(matches!(
+            unpack(&[2, 0, 0]),
+            Err(PackableError::InvalidVariant)
+        ));
+        assert!(matches!(
+            unpack(&signature().to_bytes()[..97]),
+            Err(PackableError::Io(_))
+        ));
+        assert!(matches!(unpack(&[1, 0]), Err(PackableError::Io(_))));
+    }
+
+    #[test]
+    fn unlocks() {
+        let unlocks = [signature(), Unlock::Reference { index: 0 }];
+        let mut buf = Vec::new();
+        pack_unlocks(&unlocks, &mut buf).unwrap();
+
+        assert_eq!(&buf[..2], &[2, 0]);
+        assert_eq!(&buf[2..100], &signature().to_bytes()[..]);
+        assert_eq!(&buf[100..], &[1, 0, 0]);
+
+        let mut buf = Vec::new();
+        pack_unlocks(&[], &mut buf).unwrap();
+        assert_eq!(buf, [0, 0]);
+    }
 }
@@ -1,19 +1,22 @@
+use crate::transport::Exchange;
 use crate::APDUCommand;
-use crate::Transport;
 
 use crate::api::{constants, errors, helpers};
 
-pub fn exec(
-    transport: &Transport,
-    block_number: u8,
-    data: Vec<u8>,
-) -> Result<(), errors::APIError> {
-    let cmd = APDUCommand {
+pub fn command(block_number: u8, data: Vec<u8>) -> APDUCommand<Vec<u8>> {
+    APDUCommand {
         cla: constants::APDUCLASS,
         ins: constants::APDUInstructions::WriteDataBlock as u8,
         p1: block_number,
         p2: 0,
         data,
-    };
-    helpers::exec::<()>(transport, cmd)
+    }
+}
+
+pub fn exec<T: Exchange>(
+    transport: &T,
+    block_number: u8,
+    data: Vec<u8>,
+) -> Result<(), errors::APIError> {
+    helpers::exec::<_, ()>(transport, command(block_number, data))
 }
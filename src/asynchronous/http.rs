@@ -0,0 +1,325 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::asynchronous::AsyncExchange;
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding};
+use crate::transport::errors::LedgerHTTPError;
+use crate::transport::HTTPConfig;
+
+/// Async version of [TransportHTTP](crate::ledger::ledger_transport_http::TransportHTTP)
+///
+/// Speaks just enough HTTP/1.1 for `POST /apdu` of Speculos, one connection per exchange.
+/// `ureq` has no async interface and an async HTTP client would be a large dependency for a
+/// single request type. Responses are read until the connection is closed, bodies with
+/// `Content-Length`, chunked bodies and bodies without length are supported.
+pub struct AsyncTransportHTTP {
+    host: String,
+    port: u16,
+    connect_timeout: Option<Duration>,
+    read_timeout: Mutex<Option<Duration>>,
+    encoding: Mutex<APDUEncoding>,
+}
+
+// status code and body of a complete response
+fn parse_response(raw: &[u8]) -> Result<(u16, Vec<u8>), LedgerHTTPError> {
+    let header_end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(LedgerHTTPError::ResponseError)?;
+    let head =
+        std::str::from_utf8(&raw[..header_end]).map_err(|_| LedgerHTTPError::ResponseError)?;
+    let body = &raw[header_end + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or(LedgerHTTPError::ResponseError)?;
+
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| LedgerHTTPError::ResponseError)?,
+                );
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+
+    let body = match (chunked, content_length) {
+        (true, _) => dechunk(body)?,
+        (false, Some(length)) => body
+            .get(..length)
+            .ok_or(LedgerHTTPError::ResponseError)?
+            .to_vec(),
+        // the connection is closed after the body
+        (false, None) => body.to_vec(),
+    };
+    Ok((status, body))
+}
+
+fn dechunk(mut raw: &[u8]) -> Result<Vec<u8>, LedgerHTTPError> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(LedgerHTTPError::ResponseError)?;
+        let size = std::str::from_utf8(&raw[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or(LedgerHTTPError::ResponseError)?;
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = raw
+            .get(line_end + 2..line_end + 2 + size)
+            .ok_or(LedgerHTTPError::ResponseError)?;
+        body.extend_from_slice(chunk);
+        raw = raw
+            .get(line_end + 4 + size..)
+            .ok_or(LedgerHTTPError::ResponseError)?;
+    }
+}
+
+impl AsyncTransportHTTP {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self::from_config(&HTTPConfig::new(host, port))
+    }
+
+    pub fn from_config(config: &HTTPConfig) -> Self {
+        Self {
+            host: config.host.clone(),
+            port: config.port,
+            connect_timeout: config.connect_timeout,
+            read_timeout: Mutex::new(config.read_timeout),
+            encoding: Mutex::new(APDUEncoding::Short),
+        }
+    }
+
+    /// Sets how long exchanges wait for an answer, `None` waits forever
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().expect("HTTP timeout poisoned") = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock().expect("HTTP timeout poisoned")
+    }
+
+    /// Sets how commands are serialized (see [APDUEncoding])
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        *self.encoding.lock().expect("HTTP encoding poisoned") = encoding;
+    }
+
+    pub fn apdu_encoding(&self) -> APDUEncoding {
+        *self.encoding.lock().expect("HTTP encoding poisoned")
+    }
+
+    async fn connect(&self) -> Result<TcpStream, LedgerHTTPError> {
+        let connect = TcpStream::connect((self.host.as_str(), self.port));
+        let stream = match self.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into())),
+            None => connect.await,
+        };
+        stream.map_err(LedgerHTTPError::Io)
+    }
+
+    async fn request(&self, raw_command: &[u8]) -> Result<Vec<u8>, LedgerHTTPError> {
+        let body = serde_json::json!({ "data": hex::encode(raw_command) }).to_string();
+        let request = format!(
+            "POST /apdu HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.host,
+            self.port,
+            body.len(),
+            body
+        );
+
+        let mut stream = self.connect().await?;
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .map_err(LedgerHTTPError::Io)?;
+        let mut raw_response = Vec::new();
+        stream
+            .read_to_end(&mut raw_response)
+            .await
+            .map_err(LedgerHTTPError::Io)?;
+
+        let (status, body) = parse_response(&raw_response)?;
+        if status >= 400 {
+            return Err(LedgerHTTPError::StatusError(status));
+        }
+
+        let body: serde_json::Value =
+            serde_json::from_slice(&body).map_err(|_| LedgerHTTPError::ResponseError)?;
+        // answer contains the data and the return code
+        let data = body["data"]
+            .as_str()
+            .ok_or(LedgerHTTPError::ResponseError)?;
+        hex::decode(data).map_err(|_| LedgerHTTPError::ResponseError)
+    }
+
+    pub async fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHTTPError> {
+        self.exchange_with_timeout(command, self.read_timeout())
+            .await
+    }
+
+    /// Like [AsyncTransportHTTP::exchange] with a read timeout for this call only
+    pub async fn exchange_with_timeout(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+        timeout: Option<Duration>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHTTPError> {
+        let raw_command = command
+            .serialize_with(self.apdu_encoding())
+            .map_err(|_| LedgerHTTPError::CommandTooLong)?;
+        let raw_answer = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.request(&raw_command))
+                .await
+                .map_err(|_| LedgerHTTPError::Timeout)??,
+            None => self.request(&raw_command).await?,
+        };
+        APDUAnswer::from_answer(raw_answer).map_err(|_| LedgerHTTPError::ResponseError)
+    }
+}
+
+impl AsyncExchange for AsyncTransportHTTP {
+    type Error = LedgerHTTPError;
+
+    async fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, Self::Error> {
+        self.exchange(command).await
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.apdu_encoding()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ledger::ledger_transport_http::stand_in::{self, Response};
+
+    fn command() -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: 0x7b,
+            ins: 0x10,
+            p1: 0,
+            p2: 0,
+            data: vec![0x01, 0x02],
+        }
+    }
+
+    #[tokio::test]
+    async fn apdu() {
+        let (port, requests) = stand_in::serve(vec![Response::ok(r#"{"data": "aa9000"}"#)]);
+        let transport = AsyncTransportHTTP::new("127.0.0.1", port);
+
+        let answer = transport.exchange(&command()).await.unwrap();
+        assert_eq!(answer.data(), &[0xaa]);
+        assert_eq!(answer.retcode(), 0x9000);
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/apdu");
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body, serde_json::json!({ "data": "7b100000020102" }));
+    }
+
+    #[tokio::test]
+    async fn status_error() {
+        let (port, _requests) = stand_in::serve(vec![Response {
+            status: "500 Internal Server Error",
+            ..Response::ok("{}")
+        }]);
+        let transport = AsyncTransportHTTP::new("127.0.0.1", port);
+        assert!(matches!(
+            transport.exchange(&command()).await,
+            Err(LedgerHTTPError::StatusError(500))
+        ));
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let (port, _requests) = stand_in::serve(vec![Response {
+            delay: Duration::from_secs(2),
+            ..Response::ok(r#"{"data": "9000"}"#)
+        }]);
+        let transport = AsyncTransportHTTP::new("127.0.0.1", port);
+        assert!(matches!(
+            transport
+                .exchange_with_timeout(&command(), Some(Duration::from_millis(100)))
+                .await,
+            Err(LedgerHTTPError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn chunked_response() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n{\"da\r\nc\r\nta\": \"9000\"}\r\n0\r\n\r\n";
+        let (status, body) = parse_response(raw).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, br#"{"data": "9000"}"#);
+    }
+
+    #[test]
+    fn chunk_extensions() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: Chunked\r\n\r\n4;name=value\r\n{\"da\r\nC ; x\r\nta\": \"9000\"}\r\n0\r\n\r\n";
+        let (_, body) = parse_response(raw).unwrap();
+        assert_eq!(body, br#"{"data": "9000"}"#);
+    }
+
+    #[test]
+    fn content_length() {
+        // anything after the body is ignored
+        let raw = b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\n9000xx";
+        assert_eq!(parse_response(raw).unwrap(), (200, b"9000".to_vec()));
+    }
+
+    #[test]
+    fn no_content_length() {
+        // body ends with the connection
+        let raw = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}";
+        assert_eq!(parse_response(raw).unwrap(), (200, b"{}".to_vec()));
+    }
+
+    #[test]
+    fn truncated_response() {
+        let truncated: [&[u8]; 6] = [
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n9000",
+            b"HTTP/1.1 200 OK\r\nContent-Length: x\r\n\r\n9000",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n8\r\n9000",
+            // last chunk missing
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n9000\r\n",
+            b"HTTP/1.1\r\n\r\n",
+        ];
+        for raw in truncated {
+            assert!(
+                matches!(parse_response(raw), Err(LedgerHTTPError::ResponseError)),
+                "{}",
+                String::from_utf8_lossy(raw)
+            );
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Async API (feature `async`)
+//!
+//! The TCP and HTTP transports of the simulator use async I/O, waiting for the device lock
+//! or for an answer (e.g. while the user confirms on the device) doesn't occupy a thread.
+//! HID has no async interface, HID and mock transports are run on the blocking thread
+//! pool of tokio ([BlockingTransport]).
+//!
+//! Cancellation: dropping a future only stops waiting for the result. A command (or a
+//! sequence of commands like in `get_addresses`) that already started is always run to
+//! completion in the background, so the device can't be left in the middle of a sequence.
+//! Calls on the same [AsyncLedgerHardwareWallet] are queued and run one after another.
+//! If the background task panics or the runtime shuts down, the call fails with
+//! `APIError::TaskPanicked` or `APIError::TaskCancelled`.
+
+#[cfg(feature = "speculos-http")]
+mod http;
+mod tcp;
+mod transport;
+
+use std::future::Future;
+use std::sync::Arc;
+
+use log::debug;
+
+use crate::api::constants;
+use crate::api::errors::APIError;
+use crate::api::helpers::exec_async;
+use crate::{
+    address, api, hash, validate, Callback, LedgerBIP32Index, LedgerDeviceTypes, LedgerRemainder,
+    LedgerSignedInput, TransportTypes, Unlock,
+};
+
+#[cfg(feature = "speculos-http")]
+pub use http::AsyncTransportHTTP;
+pub use tcp::AsyncTransportTCP;
+pub use transport::{
+    create_transport, AsyncExchange, AsyncLedgerTransport, AsyncTransport, BlockingTransport,
+};
+
+/// Async version of [crate::get_ledger_by_type]
+pub async fn get_ledger_by_type(
+    coin_type: u32,
+    bip32_account: u32,
+    transport_type: &TransportTypes,
+    callback: Option<Callback>,
+) -> Result<AsyncLedgerHardwareWallet, APIError> {
+    let transport = create_transport(transport_type, callback).await?;
+    get_ledger_by_transport(coin_type, bip32_account, transport).await
+}
+
+/// Async version of [crate::get_ledger_by_transport]
+pub async fn get_ledger_by_transport<T>(
+    coin_type: u32,
+    bip32_account: u32,
+    transport: T,
+) -> Result<AsyncLedgerHardwareWallet<T>, APIError>
+where
+    T: AsyncExchange + Send + Sync + 'static,
+{
+    let ledger = AsyncLedgerHardwareWallet::from_transport(transport).await?;
+
+    // set account
+    ledger.set_account(coin_type, bip32_account).await?;
+
+    Ok(ledger)
+}
+
+// state shared with the background tasks
+struct Inner<T> {
+    version: u32,
+    transport: T,
+    device_type: LedgerDeviceTypes,
+    data_buffer_size: usize,
+    is_debug_app: bool,
+    // resolved by `set_account`
+    app_mode: std::sync::Mutex<Option<constants::AppModes>>,
+}
+
+/// Async version of [LedgerHardwareWallet](crate::LedgerHardwareWallet)
+pub struct AsyncLedgerHardwareWallet<T = AsyncTransport> {
+    inner: Arc<Inner<T>>,
+    busy: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl<T> Clone for AsyncLedgerHardwareWallet<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            busy: self.busy.clone(),
+        }
+    }
+}
+
+impl<T> AsyncLedgerHardwareWallet<T>
+where
+    T: AsyncExchange + Send + Sync + 'static,
+{
+    /// Creates the object on top of an already opened transport
+    pub async fn from_transport(transport: T) -> Result<Self, APIError> {
+        // reset api
+        exec_async::<_, ()>(&transport, api::reset::command()).await?;
+
+        let res: api::get_app_config::Response =
+            exec_async(&transport, api::get_app_config::command()).await?;
+        let (version, device_type) = crate::app_info(&res)?;
+
+        let data_buffer_state: api::get_data_buffer_state::Response =
+            exec_async(&transport, api::get_data_buffer_state::command()).await?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                version,
+                transport,
+                device_type,
+                data_buffer_size: crate::buffer_size(&data_buffer_state),
+                is_debug_app: res.is_debug_app == 1,
+                app_mode: std::sync::Mutex::new(None),
+            }),
+            busy: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    // waiting for the turn can be cancelled, a started call runs to completion
+    async fn run<F, Fut, R>(&self, f: F) -> Result<R, APIError>
+    where
+        F: FnOnce(Arc<Inner<T>>) -> Fut,
+        Fut: Future<Output = Result<R, APIError>> + Send + 'static,
+        R: Send + 'static,
+    {
+        let busy = self.busy.clone().lock_owned().await;
+        let call = f(self.inner.clone());
+        tokio::spawn(async move {
+            let _busy = busy;
+            call.await
+        })
+        .await
+        .map_err(transport::join_error)?
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.inner.transport
+    }
+
+    pub fn is_debug_app(&self) -> bool {
+        self.inner.is_debug_app
+    }
+
+    pub fn device_type(&self) -> &LedgerDeviceTypes {
+        &self.inner.device_type
+    }
+
+    pub fn get_buffer_size(&self) -> usize {
+        self.inner.data_buffer_size
+    }
+
+    /// App mode selected by the last `set_account`
+    pub fn app_mode(&self) -> Option<constants::AppModes> {
+        self.inner.app_mode()
+    }
+
+    /// Bech32 HRP of the addresses of the app mode (`iota`, `atoi`, `smr` or `rms`)
+    pub fn hrp(&self) -> Option<&'static str> {
+        self.app_mode().map(|mode| mode.hrp())
+    }
+
+    /// See [LedgerHardwareWallet::validate_essence](crate::LedgerHardwareWallet::validate_essence)
+    pub fn validate_essence(
+        &self,
+        essence: &[u8],
+        key_indices: &[LedgerBIP32Index],
+        remainder: Option<&LedgerRemainder>,
+    ) -> Result<(), validate::LedgerEssenceError> {
+        validate::validate_essence(essence, key_indices, remainder, self.inner.data_buffer_size)
+    }
+
+    pub async fn is_locked(&self) -> Result<bool, APIError> {
+        self.run(|inner| async move { inner.is_locked().await })
+            .await
+    }
+
+    pub async fn reset(&self) -> Result<(), APIError> {
+        self.run(|inner| async move { exec_async(&inner.transport, api::reset::command()).await })
+            .await
+    }
+
+    pub async fn set_account(&self, coin_type: u32, bip32_account: u32) -> Result<(), APIError> {
+        self.run(move |inner| async move { inner.set_account(coin_type, bip32_account).await })
+            .await
+    }
+
+    pub async fn get_addresses(
+        &self,
+        show: bool,
+        bip32: LedgerBIP32Index,
+        count: usize,
+    ) -> Result<Vec<[u8; constants::ADDRESS_SIZE_BYTES]>, APIError> {
+        self.run(move |inner| async move { inner.get_addresses(show, bip32, count).await })
+            .await
+    }
+
+    pub async fn get_first_address(&self) -> Result<[u8; constants::ADDRESS_SIZE_BYTES], APIError> {
+        self.run(|inner| async move { inner.get_first_address().await })
+            .await
+    }
+
+    pub async fn get_bech32_addresses(
+        &self,
+        show: bool,
+        bip32: LedgerBIP32Index,
+        count: usize,
+    ) -> Result<Vec<String>, APIError> {
+        let hrp = self.required_hrp()?;
+        self.get_addresses(show, bip32, count)
+            .await?
+            .iter()
+            .map(|address| Ok(address::to_bech32(hrp, address)?))
+            .collect()
+    }
+
+    pub async fn get_first_bech32_address(&self) -> Result<String, APIError> {
+        let hrp = self.required_hrp()?;
+        Ok(address::to_bech32(hrp, &self.get_first_address().await?)?)
+    }
+
+    // checked before addresses are generated
+    fn required_hrp(&self) -> Result<&'static str, APIError> {
+        self.hrp().ok_or(APIError::AccountNotSet)
+    }
+
+    pub async fn get_public_keys(
+        &self,
+        show: bool,
+        bip32: LedgerBIP32Index,
+        count: usize,
+    ) -> Result<Vec<[u8; constants::PUBLIC_KEY_SIZE_BYTES]>, APIError> {
+        self.run(move |inner| async move { inner.get_public_keys(show, bip32, count).await })
+            .await
+    }
+
+    pub async fn prepare_signing(
+        &self,
+        key_indices: Vec<LedgerBIP32Index>,
+        essence: Vec<u8>,
+        has_remainder: bool,
+        remainder_index: u16,
+        remainder: LedgerBIP32Index,
+    ) -> Result<(), APIError> {
+        self.run(move |inner| async move {
+            inner
+                .prepare_signing(
+                    key_indices,
+                    essence,
+                    has_remainder,
+                    remainder_index,
+                    remainder,
+                )
+                .await
+        })
+        .await
+    }
+
+    pub async fn prepare_blind_signing(
+        &self,
+        key_indices: Vec<LedgerBIP32Index>,
+        essence_hash: Vec<u8>,
+    ) -> Result<(), APIError> {
+        self.run(move |inner| async move {
+            inner.prepare_blind_signing(key_indices, essence_hash).await
+        })
+        .await
+    }
+
+    /// See [LedgerHardwareWallet::prepare_blind_signing_essence](crate::LedgerHardwareWallet::prepare_blind_signing_essence)
+    pub async fn prepare_blind_signing_essence(
+        &self,
+        key_indices: Vec<LedgerBIP32Index>,
+        essence: &[u8],
+    ) -> Result<[u8; constants::ESSENCE_HASH_SIZE_BYTES], APIError> {
+        let essence_hash = hash::essence_hash(essence);
+        self.prepare_blind_signing(key_indices, essence_hash.to_vec())
+            .await?;
+        Ok(essence_hash)
+    }
+
+    /// Waits for the user to accept or reject the essence on the device
+    ///
+    /// Dropping the future doesn't abort the confirmation on the device, the next call
+    /// is run after the user made a choice (or the device timed out).
+    pub async fn user_confirm(&self) -> Result<(), APIError> {
+        self.run(
+            |inner| async move { exec_async(&inner.transport, api::user_confirm::command()).await },
+        )
+        .await
+    }
+
+    pub async fn sign(&self, num_inputs: u16) -> Result<Vec<Unlock>, APIError> {
+        crate::check_sign_count(num_inputs as usize)?;
+        self.run(move |inner| async move {
+            let mut unlocks = Vec::new();
+            for signature_idx in 0..num_inputs {
+                unlocks.push(inner.sign_single(signature_idx as u8).await?);
+            }
+            Ok(unlocks)
+        })
+        .await
+    }
+
+    /// See [LedgerHardwareWallet::sign_essence](crate::LedgerHardwareWallet::sign_essence)
+    pub async fn sign_essence(
+        &self,
+        essence: Vec<u8>,
+        inputs: Vec<LedgerBIP32Index>,
+        remainder: Option<LedgerRemainder>,
+    ) -> Result<Vec<LedgerSignedInput>, APIError> {
+        crate::check_sign_inputs(&inputs)?;
+        self.validate_essence(&essence, &inputs, remainder.as_ref())?;
+
+        self.run(move |inner| async move {
+            let res = inner.try_sign_essence(essence, inputs, remainder).await;
+            if res.is_err() {
+                // don't leave the essence or partial signatures on the device
+                if let Err(e) =
+                    exec_async::<_, ()>(&inner.transport, api::clear_data_buffer::command()).await
+                {
+                    debug!("clearing data buffer failed: {}", e);
+                }
+            }
+            res
+        })
+        .await
+    }
+}
+
+impl<T: AsyncExchange> Inner<T> {
+    fn app_mode(&self) -> Option<constants::AppModes> {
+        *self.app_mode.lock().expect("app mode poisoned")
+    }
+
+    async fn is_locked(&self) -> Result<bool, APIError> {
+        let res: Result<api::get_data_buffer_state::Response, APIError> =
+            exec_async(&self.transport, api::get_data_buffer_state::command()).await;
+        match res {
+            Err(e) if e.is_device_locked() => Ok(true),
+            Ok(_) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn set_account(&self, coin_type: u32, bip32_account: u32) -> Result<(), APIError> {
+        let app_config = exec_async(&self.transport, api::get_app_config::command()).await?;
+
+        let app_mode = api::set_account::app_mode_of(coin_type, &app_config, bip32_account)?;
+        exec_async::<_, ()>(
+            &self.transport,
+            api::set_account::command(app_mode, bip32_account),
+        )
+        .await?;
+        *self.app_mode.lock().expect("app mode poisoned") = Some(app_mode);
+        Ok(())
+    }
+
+    // see `LedgerHardwareWallet::read_data_bufer`
+    async fn read_data_buffer(&self) -> Result<Vec<u8>, APIError> {
+        let dbs: api::get_data_buffer_state::Response =
+            exec_async(&self.transport, api::get_data_buffer_state::command()).await?;
+        let blocks_needed = crate::blocks_to_read(&dbs)?;
+
+        let mut buffer: Vec<u8> = Vec::new();
+        for block in 0..blocks_needed {
+            let mut res: api::read_data_block::Response =
+                exec_async(&self.transport, api::read_data_block::command(block)).await?;
+            buffer.append(&mut res.data);
+        }
+        Ok(buffer[0..dbs.data_length as usize].to_vec())
+    }
+
+    async fn write_data_buffer(&self, data: &[u8]) -> Result<(), APIError> {
+        // clear data buffer before data can be uploaded and validated
+        exec_async::<_, ()>(&self.transport, api::clear_data_buffer::command()).await?;
+
+        let dbs = exec_async(&self.transport, api::get_data_buffer_state::command()).await?;
+        for (block, block_data) in crate::blocks_to_write(&dbs, data)? {
+            exec_async::<_, ()>(
+                &self.transport,
+                api::write_data_block::command(block, block_data),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_addresses(
+        &self,
+        show: bool,
+        bip32: LedgerBIP32Index,
+        count: usize,
+    ) -> Result<Vec<[u8; constants::ADDRESS_SIZE_BYTES]>, APIError> {
+        exec_async::<_, ()>(&self.transport, api::clear_data_buffer::command()).await?;
+
+        if count > self.data_buffer_size / constants::ADDRESS_WITH_TYPE_SIZE_BYTES {
+            return Err(APIError::CommandInvalidData);
+        }
+
+        exec_async::<_, ()>(
+            &self.transport,
+            api::generate_address::command(show, bip32, count as u32),
+        )
+        .await?;
+
+        let buffer = self.read_data_buffer().await?;
+        crate::addresses_from_buffer(&buffer, count)
+    }
+
+    async fn get_first_address(&self) -> Result<[u8; constants::ADDRESS_SIZE_BYTES], APIError> {
+        let bip32 = LedgerBIP32Index {
+            bip32_index: constants::HARDENED,
+            bip32_change: constants::HARDENED,
+        };
+        Ok(self.get_addresses(false, bip32, 1).await?[0])
+    }
+
+    async fn get_public_keys(
+        &self,
+        show: bool,
+        bip32: LedgerBIP32Index,
+        count: usize,
+    ) -> Result<Vec<[u8; constants::PUBLIC_KEY_SIZE_BYTES]>, APIError> {
+        // generate public key api call exists >= 0.8.7
+        if self.version < crate::MINIMUM_APP_VERSION_GENERATE_PUBLIC_KEYS {
+            return Err(APIError::AppTooOld);
+        }
+
+        exec_async::<_, ()>(&self.transport, api::clear_data_buffer::command()).await?;
+
+        if count > self.data_buffer_size / constants::PUBLIC_KEY_SIZE_BYTES {
+            return Err(APIError::CommandInvalidData);
+        }
+
+        exec_async::<_, ()>(
+            &self.transport,
+            api::generate_public_key::command(show, bip32, count as u32),
+        )
+        .await?;
+
+        let buffer = self.read_data_buffer().await?;
+        if buffer.len() < count * constants::PUBLIC_KEY_SIZE_BYTES {
+            return Err(APIError::CommandInvalidData);
+        }
+        Ok(buffer
+            .chunks_exact(constants::PUBLIC_KEY_SIZE_BYTES)
+            .take(count)
+            .map(|key| key.try_into().expect("32 byte chunk"))
+            .collect())
+    }
+
+    // checks that the device parsed the whole buffer
+    async fn check_parsed_length(&self, buffer_len: usize) -> Result<(), APIError> {
+        let dbs: api::get_data_buffer_state::Response =
+            exec_async(&self.transport, api::get_data_buffer_state::command()).await?;
+        if dbs.data_length != buffer_len as u16 {
+            return Err(APIError::Unknown);
+        }
+        Ok(())
+    }
+
+    async fn prepare_signing(
+        &self,
+        key_indices: Vec<LedgerBIP32Index>,
+        essence: Vec<u8>,
+        has_remainder: bool,
+        remainder_index: u16,
+        remainder: LedgerBIP32Index,
+    ) -> Result<(), APIError> {
+        let buffer = crate::signing_buffer(essence, &key_indices, self.data_buffer_size)?;
+        self.write_data_buffer(&buffer).await?;
+
+        // now validate essence
+        exec_async::<_, ()>(
+            &self.transport,
+            api::prepare_signing::command(has_remainder, remainder_index, remainder),
+        )
+        .await?;
+        self.check_parsed_length(buffer.len()).await
+    }
+
+    async fn prepare_blind_signing(
+        &self,
+        key_indices: Vec<LedgerBIP32Index>,
+        essence_hash: Vec<u8>,
+    ) -> Result<(), APIError> {
+        let buffer =
+            crate::blind_signing_buffer(essence_hash, &key_indices, self.data_buffer_size)?;
+        self.write_data_buffer(&buffer).await?;
+
+        // now validate essence
+        exec_async::<_, ()>(&self.transport, api::prepare_blind_signing::command()).await?;
+        self.check_parsed_length(buffer.len()).await
+    }
+
+    async fn sign_single(&self, signature_idx: u8) -> Result<Unlock, APIError> {
+        let signature = exec_async(&self.transport, api::sign::command(signature_idx)).await?;
+        crate::unlock_from_response(&signature)
+    }
+
+    async fn try_sign_essence(
+        &self,
+        essence: Vec<u8>,
+        inputs: Vec<LedgerBIP32Index>,
+        remainder: Option<LedgerRemainder>,
+    ) -> Result<Vec<LedgerSignedInput>, APIError> {
+        let remainder_output = remainder.unwrap_or_default();
+        self.prepare_signing(
+            inputs.clone(),
+            essence,
+            remainder.is_some(),
+            remainder_output.output_index,
+            remainder_output.bip32,
+        )
+        .await?;
+
+        exec_async::<_, ()>(&self.transport, api::user_confirm::command()).await?;
+
+        let mut signed = Vec::new();
+        for (input_index, bip32) in inputs.into_iter().enumerate() {
+            signed.push(LedgerSignedInput {
+                input_index: input_index as u16,
+                bip32,
+                unlock: self.sign_single(input_index as u8).await?,
+            });
+        }
+        Ok(signed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ledger::ledger_transport::APDUAnswer;
+    use crate::ledger::ledger_transport_mock::tests::{
+        expect_prepare_signing, key, signature_unlock,
+    };
+    use crate::transport::TransportMock;
+    use crate::validate::tests::Essence;
+
+    // buffer of 8 blocks, `data_type` and `data_length` as given
+    fn buffer_state(data_type: u8, data_length: u16) -> Vec<u8> {
+        let mut state = data_length.to_le_bytes().to_vec();
+        state.extend_from_slice(&[data_type, constants::DATA_BLOCK_SIZE as u8, 8]);
+        state
+    }
+
+    // answers of the commands sent by `from_transport` and `set_account` (IOTA app 1.0.0)
+    fn init(mock: &TransportMock) {
+        let app_config = [1, 0, 0, 0, 0, 0];
+        mock.expect_ok(api::reset::command(), &[])
+            .expect_ok(api::get_app_config::command(), &app_config)
+            .expect_ok(api::get_data_buffer_state::command(), &buffer_state(0, 0))
+            .expect_ok(api::get_app_config::command(), &app_config)
+            .expect_ok(
+                api::set_account::command(constants::AppModes::ModeIOTAStardust, 0x80000000),
+                &[],
+            );
+    }
+
+    #[tokio::test]
+    async fn mock_flow() {
+        let mock = TransportMock::new();
+        init(&mock);
+
+        let mut block = vec![0u8; constants::DATA_BLOCK_SIZE];
+        block[1..33].copy_from_slice(&[0x42; 32]);
+        let first = LedgerBIP32Index {
+            bip32_index: constants::HARDENED,
+            bip32_change: constants::HARDENED,
+        };
+        mock.expect_ok(api::clear_data_buffer::command(), &[])
+            .expect_ok(api::generate_address::command(false, first, 1), &[])
+            .expect_ok(api::get_data_buffer_state::command(), &buffer_state(1, 33))
+            .expect_ok(api::read_data_block::command(0), &block)
+            .expect_retcode(api::get_data_buffer_state::command(), 0x6982);
+
+        let ledger = get_ledger_by_type(
+            0x107a,
+            0x80000000,
+            &TransportTypes::Mock(mock.clone()),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(ledger.hrp(), Some("iota"));
+        assert_eq!(ledger.get_first_address().await.unwrap(), [0x42; 32]);
+        assert!(ledger.is_locked().await.unwrap());
+        mock.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn sign_essence() {
+        let mock = TransportMock::new();
+        init(&mock);
+        let ledger =
+            get_ledger_by_transport(0x107a, 0x80000000, BlockingTransport::new(mock.clone()))
+                .await
+                .unwrap();
+
+        let essence = Essence::new(1, 1).to_bytes();
+        expect_prepare_signing(&mock, &essence, &[key(0)], None);
+        mock.expect_ok(api::user_confirm::command(), &[])
+            .expect_ok(api::sign::command(0), &signature_unlock().to_bytes());
+        assert_eq!(
+            ledger
+                .sign_essence(essence.clone(), vec![key(0)], None)
+                .await
+                .unwrap(),
+            vec![LedgerSignedInput {
+                input_index: 0,
+                bip32: key(0),
+                unlock: signature_unlock(),
+            }]
+        );
+
+        // rejected by the user, the data buffer is cleared
+        expect_prepare_signing(&mock, &essence, &[key(0)], None);
+        mock.expect_retcode(api::user_confirm::command(), 0x6985)
+            .expect_ok(api::clear_data_buffer::command(), &[]);
+        assert!(matches!(
+            ledger.sign_essence(essence, vec![key(0)], None).await,
+            Err(APIError::ConditionsOfUseNotSatisfied)
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_call_completes() {
+        let mock = TransportMock::new();
+        init(&mock);
+        let ledger =
+            get_ledger_by_transport(0x107a, 0x80000000, BlockingTransport::new(mock.clone()))
+                .await
+                .unwrap();
+
+        // the first command of `get_first_address` blocks until it's released
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let mut started_tx = Some(started_tx);
+        let mut block = vec![0u8; constants::DATA_BLOCK_SIZE];
+        block[1..33].copy_from_slice(&[0x42; 32]);
+        mock.expect_with(move |command| {
+            assert_eq!(command.ins, api::clear_data_buffer::command().ins);
+            let _ = started_tx.take().unwrap().send(());
+            release_rx.recv().unwrap();
+            Ok(APDUAnswer::from_answer(vec![0x90, 0x00]).unwrap())
+        })
+        .expect_ok(api::generate_address::command(false, key(0), 1), &[])
+        .expect_ok(api::get_data_buffer_state::command(), &buffer_state(1, 33))
+        .expect_ok(api::read_data_block::command(0), &block)
+        .expect_ok(api::user_confirm::command(), &[]);
+
+        let first = tokio::spawn({
+            let ledger = ledger.clone();
+            async move { ledger.get_first_address().await }
+        });
+        started_rx.await.unwrap();
+        first.abort();
+        assert!(first.await.unwrap_err().is_cancelled());
+
+        // queued behind the rest of the dropped sequence
+        let next = tokio::spawn({
+            let ledger = ledger.clone();
+            async move { ledger.user_confirm().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!next.is_finished());
+        release_tx.send(()).unwrap();
+
+        next.await.unwrap().unwrap();
+        mock.verify().unwrap();
+    }
+
+    #[tokio::test]
+    async fn task_panicked() {
+        let mock = TransportMock::new();
+        init(&mock);
+        let ledger = get_ledger_by_transport(0x107a, 0x80000000, BlockingTransport::new(mock))
+            .await
+            .unwrap();
+
+        let res: Result<(), APIError> = ledger.run(|_| async move { panic!("broken flow") }).await;
+        assert!(matches!(res, Err(APIError::TaskPanicked(m)) if m == "broken flow"));
+    }
+}
@@ -0,0 +1,253 @@
+use std::future::Future;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::asynchronous::AsyncExchange;
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding};
+use crate::ledger::ledger_transport_tcp::TransportTCP;
+use crate::transport::errors::LedgerTCPError;
+use crate::transport::TCPConfig;
+
+/// Async version of [TransportTCP]
+pub struct AsyncTransportTCP {
+    url: String,
+    connect_timeout: Option<Duration>,
+    read_timeout: Mutex<Option<Duration>>,
+    write_timeout: Option<Duration>,
+    // one long-lived connection, (re-)opened on demand
+    stream: tokio::sync::Mutex<Option<TcpStream>>,
+    encoding: Mutex<APDUEncoding>,
+}
+
+async fn with_timeout<F, R>(timeout: Option<Duration>, f: F) -> std::io::Result<R>
+where
+    F: Future<Output = std::io::Result<R>>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, f)
+            .await
+            .map_err(|_| std::io::Error::from(ErrorKind::TimedOut))?,
+        None => f.await,
+    }
+}
+
+// a connection that waits for the next command has nothing to read, anything else
+// (end of stream, error, stray bytes) means it can't be used anymore
+fn is_stale(stream: &TcpStream) -> bool {
+    !matches!(stream.try_read(&mut [0u8; 1]), Err(e) if e.kind() == ErrorKind::WouldBlock)
+}
+
+impl AsyncTransportTCP {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self::from_config(&TCPConfig::new(host, port))
+    }
+
+    pub fn from_config(config: &TCPConfig) -> Self {
+        Self {
+            url: format!("{}:{}", config.host, config.port),
+            connect_timeout: config.connect_timeout,
+            read_timeout: Mutex::new(config.read_timeout),
+            write_timeout: config.write_timeout,
+            stream: tokio::sync::Mutex::new(None),
+            encoding: Mutex::new(APDUEncoding::Short),
+        }
+    }
+
+    /// Sets how long exchanges wait for an answer, `None` waits forever
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().expect("TCP timeout poisoned") = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock().expect("TCP timeout poisoned")
+    }
+
+    /// Sets how commands are serialized (see [APDUEncoding])
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        *self.encoding.lock().expect("TCP encoding poisoned") = encoding;
+    }
+
+    pub fn apdu_encoding(&self) -> APDUEncoding {
+        *self.encoding.lock().expect("TCP encoding poisoned")
+    }
+
+    async fn connect(&self) -> Result<TcpStream, LedgerTCPError> {
+        let stream = with_timeout(self.connect_timeout, TcpStream::connect(&self.url))
+            .await
+            .map_err(TransportTCP::connect_error)?;
+        stream
+            .set_nodelay(true)
+            .map_err(LedgerTCPError::ConnectError)?;
+
+        log::debug!("successfully connected to server {}", &self.url);
+        Ok(stream)
+    }
+
+    async fn read_answer(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+        // first read number of bytes
+        let mut rcv_length_bytes = [0u8; 4];
+        stream.read_exact(&mut rcv_length_bytes).await?;
+
+        let mut buf = vec![0u8; TransportTCP::answer_length(rcv_length_bytes)?];
+        stream.read_exact(&mut buf).await?;
+        Ok(buf)
+    }
+
+    pub async fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerTCPError> {
+        self.exchange_with_timeout(command, self.read_timeout())
+            .await
+    }
+
+    /// Like [AsyncTransportTCP::exchange] with a read timeout for this call only
+    ///
+    /// If the future is dropped while the exchange runs, the connection is closed and
+    /// reopened with the next exchange.
+    pub async fn exchange_with_timeout(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+        timeout: Option<Duration>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerTCPError> {
+        let frame = TransportTCP::frame(
+            &command
+                .serialize_with(self.apdu_encoding())
+                .map_err(|_| LedgerTCPError::CommandTooLong)?,
+        );
+
+        let mut guard = self.stream.lock().await;
+
+        // taken out while in use and only put back after a complete exchange
+        let mut stream = match guard.take() {
+            Some(stream) if !is_stale(&stream) => stream,
+            Some(_) => {
+                log::debug!("connection to {} lost, reconnecting", &self.url);
+                self.connect().await?
+            }
+            None => self.connect().await?,
+        };
+
+        with_timeout(self.write_timeout, stream.write_all(&frame))
+            .await
+            .map_err(TransportTCP::map_io_error)?;
+        // the command was sent, it is never sent again from here on
+        let raw_answer = with_timeout(timeout, Self::read_answer(&mut stream))
+            .await
+            .map_err(TransportTCP::map_io_error)?;
+        *guard = Some(stream);
+        drop(guard);
+
+        APDUAnswer::from_answer(raw_answer).map_err(|_| LedgerTCPError::ResponseError)
+    }
+}
+
+impl AsyncExchange for AsyncTransportTCP {
+    type Error = LedgerTCPError;
+
+    async fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, Self::Error> {
+        self.exchange(command).await
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.apdu_encoding()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn command() -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: 0x7b,
+            ins: 0x10,
+            p1: 0,
+            p2: 0,
+            data: vec![0x01, 0x02],
+        }
+    }
+
+    // answers each connection with the given raw answers, one per command
+    fn serve(connections: Vec<Vec<Vec<u8>>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for answers in connections {
+                let (mut stream, _) = listener.accept().unwrap();
+                for answer in answers {
+                    let mut header = [0u8; 4];
+                    stream.read_exact(&mut header).unwrap();
+                    let mut command = vec![0u8; u32::from_be_bytes(header) as usize];
+                    stream.read_exact(&mut command).unwrap();
+                    stream.write_all(&answer).unwrap();
+                }
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn exchange() {
+        let port = serve(vec![vec![vec![0, 0, 0, 1, 0xaa, 0x90, 0x00]]]);
+        let transport = AsyncTransportTCP::new("127.0.0.1", port);
+
+        let answer = transport.exchange(&command()).await.unwrap();
+        assert_eq!(answer.data(), &[0xaa]);
+        assert_eq!(answer.retcode(), 0x9000);
+    }
+
+    #[tokio::test]
+    async fn reconnect_after_close() {
+        // first connection is closed after one answer
+        let port = serve(vec![
+            vec![vec![0, 0, 0, 0, 0x90, 0x00]],
+            vec![vec![0, 0, 0, 0, 0x69, 0x85]],
+        ]);
+        let transport = AsyncTransportTCP::new("127.0.0.1", port);
+
+        assert_eq!(
+            transport.exchange(&command()).await.unwrap().retcode(),
+            0x9000
+        );
+        // give the server some time to close the connection
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            transport.exchange(&command()).await.unwrap().retcode(),
+            0x6985
+        );
+    }
+
+    #[tokio::test]
+    async fn answer_too_long() {
+        let port = serve(vec![vec![vec![0xff, 0xff, 0xff, 0xff]]]);
+        let transport = AsyncTransportTCP::new("127.0.0.1", port);
+        assert!(matches!(
+            transport.exchange(&command()).await,
+            Err(LedgerTCPError::ResponseError)
+        ));
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = AsyncTransportTCP::new("127.0.0.1", listener.local_addr().unwrap().port());
+        assert!(matches!(
+            transport
+                .exchange_with_timeout(&command(), Some(Duration::from_millis(100)))
+                .await,
+            Err(LedgerTCPError::Timeout)
+        ));
+    }
+}
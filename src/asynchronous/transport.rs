@@ -0,0 +1,311 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use crate::api::errors::APIError;
+#[cfg(feature = "speculos-http")]
+use crate::asynchronous::http::AsyncTransportHTTP;
+use crate::asynchronous::tcp::AsyncTransportTCP;
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding};
+use crate::transport::lock::{self, DeviceLockGuard};
+use crate::transport::observer::CallbackObserver;
+use crate::transport::{Callback, Exchange, Observer, Transport, TransportTypes};
+
+/// Async counterpart of [Exchange]
+pub trait AsyncExchange {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Send a command and wait for the answer
+    fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> impl Future<Output = Result<APDUAnswer<Vec<u8>>, Self::Error>> + Send;
+
+    /// How commands are serialized, used to check the length before sending
+    fn apdu_encoding(&self) -> APDUEncoding {
+        APDUEncoding::Short
+    }
+}
+
+pub(crate) fn join_error(e: tokio::task::JoinError) -> APIError {
+    if !e.is_panic() {
+        // runtime is shutting down
+        return APIError::TaskCancelled;
+    }
+    let panic = e.into_panic();
+    let message = match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+    };
+    APIError::TaskPanicked(message)
+}
+
+pub(crate) async fn run_blocking<F, R>(f: F) -> Result<R, APIError>
+where
+    F: FnOnce() -> Result<R, APIError> + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(join_error)?
+}
+
+/// Runs a blocking [Exchange] on the blocking thread pool of tokio
+///
+/// For transports without async I/O (HID, mock). Exchanges are run one after another,
+/// a started exchange is completed even if the returned future is dropped.
+pub struct BlockingTransport<T> {
+    transport: Arc<T>,
+    busy: Arc<Mutex<()>>,
+}
+
+impl<T> Clone for BlockingTransport<T> {
+    fn clone(&self) -> Self {
+        Self {
+            transport: self.transport.clone(),
+            busy: self.busy.clone(),
+        }
+    }
+}
+
+impl<T: Exchange + Send + Sync + 'static> BlockingTransport<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            busy: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// The wrapped transport, e.g. to change its settings
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    /// Runs `f` with the transport once the previous call is done
+    pub async fn run<F, R>(&self, f: F) -> Result<R, APIError>
+    where
+        F: FnOnce(&T) -> Result<R, APIError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let busy = self.busy.clone().lock_owned().await;
+        let transport = self.transport.clone();
+        run_blocking(move || {
+            let _busy = busy;
+            f(&transport)
+        })
+        .await
+    }
+}
+
+impl<T> AsyncExchange for BlockingTransport<T>
+where
+    T: Exchange<AnswerType = Vec<u8>> + Send + Sync + 'static,
+{
+    type Error = APIError;
+
+    async fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, Self::Error> {
+        let command = command.clone();
+        self.run(move |transport| {
+            transport.exchange(&command).map_err(|e| {
+                log::error!("error: {}", e);
+                APIError::from_transport_error(e)
+            })
+        })
+        .await
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.transport.apdu_encoding()
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub enum AsyncLedgerTransport {
+    TCP(AsyncTransportTCP),
+    #[cfg(feature = "speculos-http")]
+    HTTP(AsyncTransportHTTP),
+    /// HID and mock transports
+    Blocking(BlockingTransport<Transport>),
+}
+
+/// Async version of [Transport]
+///
+/// TCP and HTTP use async I/O, HID and mock transports are run with [BlockingTransport].
+/// The retry policy of [Transport] is only applied to the latter.
+pub struct AsyncTransport {
+    pub transport: AsyncLedgerTransport,
+    transport_type: TransportTypes,
+    observers: Vec<Arc<dyn Observer>>,
+    // HID and mock transports hold the lock themselves
+    _lock: Option<DeviceLockGuard>,
+}
+
+async fn observe<F, E>(
+    command: &APDUCommand<Vec<u8>>,
+    observers: &[Arc<dyn Observer>],
+    exchange: F,
+) -> Result<APDUAnswer<Vec<u8>>, E>
+where
+    F: Future<Output = Result<APDUAnswer<Vec<u8>>, E>>,
+    E: std::error::Error + 'static,
+{
+    observers.iter().for_each(|o| o.on_request(command));
+
+    match exchange.await {
+        Ok(answer) => {
+            observers.iter().for_each(|o| o.on_answer(command, &answer));
+            Ok(answer)
+        }
+        Err(e) => {
+            observers.iter().for_each(|o| o.on_error(command, &e));
+            Err(e)
+        }
+    }
+}
+
+impl AsyncTransport {
+    pub fn transport_type(&self) -> TransportTypes {
+        self.transport_type.clone()
+    }
+
+    /// Registers an observer that is notified about every exchange of this transport
+    pub fn add_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Sets how long exchanges wait for an answer, see [Transport::set_read_timeout]
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        match &self.transport {
+            AsyncLedgerTransport::TCP(t) => t.set_read_timeout(timeout),
+            #[cfg(feature = "speculos-http")]
+            AsyncLedgerTransport::HTTP(t) => t.set_read_timeout(timeout),
+            AsyncLedgerTransport::Blocking(b) => b.get_ref().set_read_timeout(timeout),
+        }
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        match &self.transport {
+            AsyncLedgerTransport::TCP(t) => t.read_timeout(),
+            #[cfg(feature = "speculos-http")]
+            AsyncLedgerTransport::HTTP(t) => t.read_timeout(),
+            AsyncLedgerTransport::Blocking(b) => b.get_ref().read_timeout(),
+        }
+    }
+
+    /// Sets how commands are serialized, see [Transport::set_apdu_encoding]
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        match &self.transport {
+            AsyncLedgerTransport::TCP(t) => t.set_apdu_encoding(encoding),
+            #[cfg(feature = "speculos-http")]
+            AsyncLedgerTransport::HTTP(t) => t.set_apdu_encoding(encoding),
+            AsyncLedgerTransport::Blocking(b) => b.get_ref().set_apdu_encoding(encoding),
+        }
+    }
+
+    /// Like [AsyncExchange::exchange] with a read timeout for this call only
+    ///
+    /// Fails with `APIError::Timeout` if there was no answer in time.
+    pub async fn exchange_with_timeout(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+        timeout: Option<Duration>,
+    ) -> Result<APDUAnswer<Vec<u8>>, APIError> {
+        match &self.transport {
+            AsyncLedgerTransport::TCP(t) => observe(
+                command,
+                &self.observers,
+                t.exchange_with_timeout(command, timeout),
+            )
+            .await
+            .map_err(crate::transport::tcp_error),
+            #[cfg(feature = "speculos-http")]
+            AsyncLedgerTransport::HTTP(t) => observe(
+                command,
+                &self.observers,
+                t.exchange_with_timeout(command, timeout),
+            )
+            .await
+            .map_err(crate::transport::http_error),
+            AsyncLedgerTransport::Blocking(b) => {
+                let owned = command.clone();
+                observe(
+                    command,
+                    &self.observers,
+                    b.run(move |t| t.exchange_with_timeout(&owned, timeout)),
+                )
+                .await
+            }
+        }
+    }
+}
+
+impl AsyncExchange for AsyncTransport {
+    type Error = APIError;
+
+    async fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, Self::Error> {
+        self.exchange_with_timeout(command, self.read_timeout())
+            .await
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        match &self.transport {
+            AsyncLedgerTransport::TCP(t) => t.apdu_encoding(),
+            #[cfg(feature = "speculos-http")]
+            AsyncLedgerTransport::HTTP(t) => t.apdu_encoding(),
+            AsyncLedgerTransport::Blocking(b) => b.get_ref().apdu_encoding(),
+        }
+    }
+}
+
+/// Async version of [crate::transport::create_transport]
+///
+/// Waiting for a simulator that is in use doesn't block a thread. HID and mock transports
+/// are opened on the blocking thread pool.
+pub async fn create_transport(
+    transport_type: &TransportTypes,
+    callback: Option<Callback>,
+) -> Result<AsyncTransport, APIError> {
+    let observers: Vec<Arc<dyn Observer>> = match callback {
+        Some(callback) => vec![Arc::new(CallbackObserver(callback))],
+        None => Vec::new(),
+    };
+
+    let (device_lock, transport) = match transport_type {
+        TransportTypes::TCP(config) => (
+            Some(lock_speculos(&config.lock_key()).await?),
+            AsyncLedgerTransport::TCP(AsyncTransportTCP::from_config(config)),
+        ),
+        #[cfg(feature = "speculos-http")]
+        TransportTypes::HTTP(config) => (
+            Some(lock_speculos(&config.lock_key()).await?),
+            AsyncLedgerTransport::HTTP(AsyncTransportHTTP::from_config(config)),
+        ),
+        TransportTypes::NativeHID(_) | TransportTypes::Mock(_) => {
+            let transport_type = transport_type.clone();
+            let transport =
+                run_blocking(move || crate::transport::create_transport(&transport_type, None))
+                    .await?;
+            (
+                None,
+                AsyncLedgerTransport::Blocking(BlockingTransport::new(transport)),
+            )
+        }
+    };
+
+    Ok(AsyncTransport {
+        transport,
+        transport_type: transport_type.clone(),
+        observers,
+        _lock: device_lock,
+    })
+}
+
+async fn lock_speculos(key: &str) -> Result<DeviceLockGuard, APIError> {
+    lock::lock_async(key, crate::transport::TRANSPORT_LOCK_TIMEOUT).await
+}
@@ -0,0 +1,114 @@
+//! Serves a Ledger attached via USB on the TCP port of the Speculos simulator
+//!
+//! Usage: `ledger-bridge [--host HOST] [--port PORT] [--serial SERIAL | --path PATH] [--list]`
+
+use std::net::TcpListener;
+use std::process::exit;
+
+use iota_ledger_nano::transport::{create_transport, HIDSelector, TransportTypes};
+
+const USAGE: &str =
+    "usage: ledger-bridge [--host HOST] [--port PORT] [--serial SERIAL | --path PATH] [--list]
+
+Serves the Ledger device on the TCP protocol of the Speculos simulator.
+
+    --host HOST       address to listen on (default 127.0.0.1)
+    --port PORT       port to listen on (default 9999)
+    --serial SERIAL   use the device with the given serial number
+    --path PATH       use the device with the given HID path
+    --list            list the attached devices and exit";
+
+struct Args {
+    host: String,
+    port: u16,
+    selector: HIDSelector,
+    list: bool,
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    exit(2);
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        host: String::from("127.0.0.1"),
+        port: 9999,
+        selector: HIDSelector::First,
+        list: false,
+    };
+
+    let mut it = std::env::args().skip(1);
+    while let Some(arg) = it.next() {
+        let mut value = || {
+            it.next()
+                .unwrap_or_else(|| usage_error(&format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--host" => args.host = value(),
+            "--port" => {
+                args.port = value()
+                    .parse()
+                    .unwrap_or_else(|_| usage_error("invalid port"))
+            }
+            "--serial" => args.selector = HIDSelector::Serial(value()),
+            "--path" => args.selector = HIDSelector::Path(value()),
+            "--list" => args.list = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => usage_error(&format!("unknown argument {}", arg)),
+        }
+    }
+    args
+}
+
+fn main() {
+    let args = parse_args();
+
+    if args.list {
+        match iota_ledger_nano::list_ledger_devices() {
+            Ok(devices) => {
+                for device in devices {
+                    println!(
+                        "{} serial: {} product: {} ({:#06x})",
+                        device.path,
+                        device.serial_number.as_deref().unwrap_or("-"),
+                        device.product.as_deref().unwrap_or("-"),
+                        device.product_id
+                    );
+                }
+            }
+            Err(e) => {
+                eprintln!("can't list devices: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    let transport = match create_transport(&TransportTypes::NativeHID(args.selector), None) {
+        Ok(transport) => transport,
+        Err(e) => {
+            eprintln!("can't open device: {}", e);
+            exit(1);
+        }
+    };
+    // commands like user_confirm wait for the user
+    transport.set_read_timeout(None);
+
+    let listener = match TcpListener::bind((args.host.as_str(), args.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("can't listen on {}:{}: {}", args.host, args.port, e);
+            exit(1);
+        }
+    };
+    eprintln!("serving the device on {}:{}", args.host, args.port);
+
+    if let Err(e) = iota_ledger_nano::bridge::serve(&listener, &transport) {
+        eprintln!("bridge failed: {}", e);
+        exit(1);
+    }
+}
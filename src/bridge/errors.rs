@@ -0,0 +1,11 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LedgerBridgeError {
+    /// client connection failed
+    #[error("Bridge: i/o error: {0}")]
+    Io(#[from] std::io::Error),
+    /// device behind the bridge failed, the client connection is closed
+    #[error("Bridge: transport error: {0}")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
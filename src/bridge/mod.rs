@@ -0,0 +1,200 @@
+//! Serves a transport over the TCP protocol of Speculos (APDU port)
+//!
+//! Tools that only speak the simulator protocol (like [crate::transport::TransportTypes::TCP])
+//! can drive a real device this way. Commands are length prefixed (4 bytes big endian), answers
+//! are prefixed with the length of the data without the 2 bytes of the status word.
+
+mod errors;
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+pub use errors::LedgerBridgeError;
+
+use crate::ledger::ledger_apdu::APDUCommand;
+use crate::transport::Exchange;
+
+// "incorrect length" for commands that can't be parsed
+const RETCODE_INVALID_LENGTH: u16 = 0x6700;
+// header and extended Lc with the maximum payload
+const MAX_COMMAND_LENGTH: usize = 7 + 0xffff;
+
+/// Accepts clients one after another and forwards their commands to `transport`
+///
+/// Only returns if the listener fails. Errors of single clients are logged and their
+/// connection is closed.
+pub fn serve<T: Exchange>(listener: &TcpListener, transport: &T) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept()?;
+        log::info!("client {} connected", addr);
+
+        match serve_client(stream, transport) {
+            Ok(()) => log::info!("client {} disconnected", addr),
+            Err(e) => log::error!("client {}: {}", addr, e),
+        }
+    }
+}
+
+/// Forwards the commands of a single client until it disconnects
+pub fn serve_client<T: Exchange>(
+    mut stream: TcpStream,
+    transport: &T,
+) -> Result<(), LedgerBridgeError> {
+    stream.set_nodelay(true)?;
+
+    while let Some(raw_command) = read_command(&mut stream)? {
+        log::debug!(">> {}", hex::encode(&raw_command));
+
+        let command = match APDUCommand::from_raw(&raw_command) {
+            Ok(command) => command,
+            Err(e) => {
+                log::warn!("invalid command: {}", e);
+                write_answer(&mut stream, &[], RETCODE_INVALID_LENGTH)?;
+                continue;
+            }
+        };
+
+        let answer = transport
+            .exchange(&command)
+            .map_err(|e| LedgerBridgeError::Transport(Box::new(e)))?;
+
+        log::debug!("<< {} {:04x}", hex::encode(answer.data()), answer.retcode());
+        write_answer(&mut stream, answer.data(), answer.retcode())?;
+    }
+    Ok(())
+}
+
+// `None` if the client closed the connection
+fn read_command(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut length_bytes = [0u8; 4];
+    match stream.read_exact(&mut length_bytes) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let length = u32::from_be_bytes(length_bytes) as usize;
+    if length > MAX_COMMAND_LENGTH {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("command too long ({} bytes)", length),
+        ));
+    }
+
+    let mut raw_command = vec![0u8; length];
+    stream.read_exact(&mut raw_command)?;
+    Ok(Some(raw_command))
+}
+
+fn write_answer(stream: &mut TcpStream, data: &[u8], retcode: u16) -> std::io::Result<()> {
+    let mut raw_answer = Vec::with_capacity(4 + data.len() + 2);
+    // length without the status word
+    raw_answer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    raw_answer.extend_from_slice(data);
+    raw_answer.extend_from_slice(&retcode.to_be_bytes());
+    stream.write_all(&raw_answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::thread;
+
+    use crate::ledger::ledger_transport_mock::TransportMock;
+    use crate::ledger::ledger_transport_tcp::TransportTCP;
+
+    fn command(data: &[u8]) -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: 0x7b,
+            ins: 0x10,
+            p1: 0,
+            p2: 0,
+            data: data.to_vec(),
+        }
+    }
+
+    // serves `connections` clients, the results of the clients are sent back
+    fn bridge(
+        mock: &TransportMock,
+        connections: usize,
+    ) -> (u16, mpsc::Receiver<Result<(), LedgerBridgeError>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        let mock = mock.clone();
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                tx.send(serve_client(stream, &mock)).unwrap();
+            }
+        });
+        (port, rx)
+    }
+
+    fn send_raw(stream: &mut TcpStream, raw_command: &[u8]) -> Vec<u8> {
+        stream
+            .write_all(&(raw_command.len() as u32).to_be_bytes())
+            .unwrap();
+        stream.write_all(raw_command).unwrap();
+
+        let mut length_bytes = [0u8; 4];
+        stream.read_exact(&mut length_bytes).unwrap();
+        let mut raw_answer = vec![0u8; u32::from_be_bytes(length_bytes) as usize + 2];
+        stream.read_exact(&mut raw_answer).unwrap();
+        raw_answer
+    }
+
+    #[test]
+    fn tcp_client() {
+        let mock = TransportMock::new();
+        mock.expect_ok(command(&[0x01]), &[0xaa, 0xbb])
+            .expect_retcode(command(&[0x02]), 0x6985);
+        let (port, results) = bridge(&mock, 1);
+
+        let transport = TransportTCP::new("127.0.0.1", port);
+        let answer = transport.exchange(&command(&[0x01])).unwrap();
+        assert_eq!(answer.data(), &[0xaa, 0xbb]);
+        assert_eq!(answer.retcode(), 0x9000);
+        let answer = transport.exchange(&command(&[0x02])).unwrap();
+        assert!(answer.data().is_empty());
+        assert_eq!(answer.retcode(), 0x6985);
+
+        drop(transport);
+        results.recv().unwrap().unwrap();
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn bad_length() {
+        let mock = TransportMock::new();
+        mock.expect_ok(command(&[0x01]), &[0xaa]);
+        let (port, results) = bridge(&mock, 2);
+
+        // Lc says 5 bytes, there is 1
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        assert_eq!(
+            send_raw(&mut stream, &[0x7b, 0x10, 0x00, 0x00, 0x05, 0x01]),
+            [0x67, 0x00]
+        );
+        // the connection can still be used
+        assert_eq!(
+            send_raw(&mut stream, &[0x7b, 0x10, 0x00, 0x00, 0x01, 0x01]),
+            [0xaa, 0x90, 0x00]
+        );
+        drop(stream);
+        results.recv().unwrap().unwrap();
+
+        // frame length over the maximum closes the connection
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .write_all(&(MAX_COMMAND_LENGTH as u32 + 1).to_be_bytes())
+            .unwrap();
+        assert!(matches!(
+            results.recv().unwrap(),
+            Err(LedgerBridgeError::Io(e)) if e.kind() == ErrorKind::InvalidData
+        ));
+        mock.verify().unwrap();
+    }
+}
@@ -0,0 +1,25 @@
+//! Blake2b-256 hashes used by the IOTA and Shimmer apps
+
+use blake2::digest::{Update, VariableOutput};
+use blake2::VarBlake2b;
+
+use crate::api::constants::{ADDRESS_SIZE_BYTES, ESSENCE_HASH_SIZE_BYTES};
+
+pub(crate) fn blake2b_256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = VarBlake2b::new(32).expect("valid output size");
+    hasher.update(data);
+    let mut hash = [0u8; 32];
+    hasher.finalize_variable(|res| hash.clone_from_slice(res));
+    hash
+}
+
+/// Blake2b-256 hash of the serialized essence (the value that is signed and shown for blind
+/// signing)
+pub fn essence_hash(essence: &[u8]) -> [u8; ESSENCE_HASH_SIZE_BYTES] {
+    blake2b_256(essence)
+}
+
+/// Ed25519 address (without type byte) of a public key
+pub fn address_from_public_key(public_key: &[u8; 32]) -> [u8; ADDRESS_SIZE_BYTES] {
+    blake2b_256(public_key)
+}
@@ -13,7 +13,7 @@
 *  See the License for the specific language governing permissions and
 *  limitations under the License.
 ********************************************************************************/
-// changes: removed code we don't need
+// changes: removed code we don't need, checked and extended length serialization
 //! This crate contains a couple of utilities to talk via the APDU protocol to Ledger devices
 
 use core::ops::Deref;
@@ -35,16 +35,106 @@ pub struct APDUCommand<B> {
     pub data: B,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Encoding of the length of the payload (Lc)
+pub enum APDUEncoding {
+    /// 1 byte Lc, payload up to 255 bytes
+    #[default]
+    Short,
+    /// 3 byte Lc (`0x00` followed by the 16bit length), payload up to 65535 bytes
+    ///
+    /// Only for apps that accept extended length APDUs.
+    Extended,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Error serializing an APDU command
+pub enum APDUCommandError {
+    /// Payload length doesn't fit into Lc
+    DataTooLong(usize),
+    /// Lc doesn't match the length of the payload
+    InvalidLength,
+}
+
+impl std::fmt::Display for APDUCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            APDUCommandError::DataTooLong(len) => write!(f, "APDU data too long ({} bytes)", len),
+            APDUCommandError::InvalidLength => write!(f, "invalid APDU length"),
+        }
+    }
+}
+
+impl std::error::Error for APDUCommandError {}
+
 impl<B> APDUCommand<B>
 where
     B: Deref<Target = [u8]>,
 {
     /// Serialize this [APDUCommand] to be sent to the device
+    ///
+    /// The length isn't checked, a payload over 255 bytes gets a wrong Lc.
+    /// Use [APDUCommand::try_serialize] instead.
     pub fn serialize(&self) -> std::vec::Vec<u8> {
         let mut v = std::vec![self.cla, self.ins, self.p1, self.p2, self.data.len() as u8];
         v.extend(self.data.iter());
         v
     }
+
+    /// Serialize this [APDUCommand] with a short Lc, fails if the payload is over 255 bytes
+    pub fn try_serialize(&self) -> Result<std::vec::Vec<u8>, APDUCommandError> {
+        self.serialize_with(APDUEncoding::Short)
+    }
+
+    /// Serialize this [APDUCommand] with the given length encoding
+    pub fn serialize_with(
+        &self,
+        encoding: APDUEncoding,
+    ) -> Result<std::vec::Vec<u8>, APDUCommandError> {
+        let too_long = || APDUCommandError::DataTooLong(self.data.len());
+
+        let mut v = std::vec![self.cla, self.ins, self.p1, self.p2];
+        match encoding {
+            APDUEncoding::Short => {
+                v.push(u8::try_from(self.data.len()).map_err(|_| too_long())?);
+            }
+            APDUEncoding::Extended => {
+                let len = u16::try_from(self.data.len()).map_err(|_| too_long())?;
+                v.push(0x00);
+                v.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+        v.extend(self.data.iter());
+        Ok(v)
+    }
+}
+
+impl APDUCommand<std::vec::Vec<u8>> {
+    /// Parse a serialized command (short or extended Lc)
+    pub fn from_raw(raw: &[u8]) -> Result<Self, APDUCommandError> {
+        if raw.len() < 5 {
+            return Err(APDUCommandError::InvalidLength);
+        }
+
+        let data = if raw[4] as usize == raw.len() - 5 {
+            &raw[5..]
+        } else if raw[4] == 0
+            && raw.len() >= 7
+            && u16::from_be_bytes([raw[5], raw[6]]) as usize == raw.len() - 7
+        {
+            &raw[7..]
+        } else {
+            return Err(APDUCommandError::InvalidLength);
+        };
+
+        Ok(APDUCommand {
+            cla: raw[0],
+            ins: raw[1],
+            p1: raw[2],
+            p2: raw[3],
+            data: data.to_vec(),
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -97,3 +187,80 @@ where
         self.retcode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(length: usize) -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: 0x7b,
+            ins: 0x10,
+            p1: 0x01,
+            p2: 0x02,
+            data: vec![0xaa; length],
+        }
+    }
+
+    #[test]
+    fn short() {
+        let raw = command(2).serialize_with(APDUEncoding::Short).unwrap();
+        assert_eq!(raw, [0x7b, 0x10, 0x01, 0x02, 0x02, 0xaa, 0xaa]);
+        assert_eq!(APDUCommand::from_raw(&raw).unwrap().data, command(2).data);
+
+        let raw = command(255).serialize_with(APDUEncoding::Short).unwrap();
+        assert_eq!(raw[4], 0xff);
+        assert_eq!(APDUCommand::from_raw(&raw).unwrap().data.len(), 255);
+
+        assert_eq!(
+            command(256).serialize_with(APDUEncoding::Short),
+            Err(APDUCommandError::DataTooLong(256))
+        );
+    }
+
+    #[test]
+    fn extended() {
+        let raw = command(2).serialize_with(APDUEncoding::Extended).unwrap();
+        assert_eq!(raw, [0x7b, 0x10, 0x01, 0x02, 0x00, 0x00, 0x02, 0xaa, 0xaa]);
+        assert_eq!(APDUCommand::from_raw(&raw).unwrap().data, command(2).data);
+
+        let raw = command(300).serialize_with(APDUEncoding::Extended).unwrap();
+        assert_eq!(&raw[4..7], &[0x00, 0x01, 0x2c]);
+        let parsed = APDUCommand::from_raw(&raw).unwrap();
+        assert_eq!(
+            (parsed.cla, parsed.ins, parsed.p1, parsed.p2),
+            (0x7b, 0x10, 0x01, 0x02)
+        );
+        assert_eq!(parsed.data, command(300).data);
+
+        assert_eq!(
+            command(65536).serialize_with(APDUEncoding::Extended),
+            Err(APDUCommandError::DataTooLong(65536))
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let raw = command(0).serialize_with(APDUEncoding::Short).unwrap();
+        assert_eq!(raw, [0x7b, 0x10, 0x01, 0x02, 0x00]);
+        assert!(APDUCommand::from_raw(&raw).unwrap().data.is_empty());
+    }
+
+    #[test]
+    fn invalid_length() {
+        assert_eq!(
+            APDUCommand::from_raw(&[0x7b, 0x10, 0x01, 0x02]).err(),
+            Some(APDUCommandError::InvalidLength)
+        );
+        // Lc says 3 bytes, there are 2
+        assert_eq!(
+            APDUCommand::from_raw(&[0x7b, 0x10, 0x01, 0x02, 0x03, 0xaa, 0xaa]).err(),
+            Some(APDUCommandError::InvalidLength)
+        );
+        // extended Lc says 3 bytes, there are 2
+        assert_eq!(
+            APDUCommand::from_raw(&[0x7b, 0x10, 0x01, 0x02, 0x00, 0x00, 0x03, 0xaa, 0xaa]).err(),
+            Some(APDUCommandError::InvalidLength)
+        );
+    }
+}
@@ -13,7 +13,8 @@
 *  See the License for the specific language governing permissions and
 *  limitations under the License.
 ********************************************************************************/
-// changes: removed async
+// changes: removed async, error type bound so it can be passed on to the caller, apdu encoding,
+// retry policy
 //! Generic APDU transport library for Ledger Nano S/X apps
 
 #![deny(trivial_casts, trivial_numeric_casts)]
@@ -22,12 +23,13 @@
 
 use std::ops::Deref;
 
-pub use crate::ledger::ledger_apdu::{APDUAnswer, APDUCommand};
+pub use crate::ledger::ledger_apdu::{APDUAnswer, APDUCommand, APDUEncoding};
+use crate::transport::RetryPolicy;
 
 /// Use to talk to the ledger device
 pub trait Exchange {
     /// Error defined by Transport used
-    type Error;
+    type Error: std::error::Error + Send + Sync + 'static;
 
     /// The concrete type containing the APDUAnswer
     type AnswerType: Deref<Target = [u8]> + Send;
@@ -39,4 +41,26 @@ pub trait Exchange {
     ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
     where
         I: Deref<Target = [u8]> + Send + Sync;
+
+    /// Length encoding the transport uses to serialize commands
+    fn apdu_encoding(&self) -> APDUEncoding {
+        APDUEncoding::Short
+    }
+
+    /// Policy for retrying failed sequences of commands (e.g. the data buffer upload)
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Like [Exchange::exchange] but sends the command only once, for sequences of commands
+    /// that are retried as a whole
+    fn exchange_once<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.exchange(command)
+    }
 }
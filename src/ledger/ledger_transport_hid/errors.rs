@@ -20,16 +20,107 @@ pub enum LedgerHIDError {
     /// Device not found error
     #[error("Ledger device not found")]
     DeviceNotFound,
+    /// Device can't be opened (e.g. missing permissions or in use)
+    #[error("Ledger device: can't open {path}: {source}")]
+    OpenFailed {
+        path: String,
+        source: hidapi::HidError,
+    },
     /// Communication error
     #[error("Ledger device: communication error `{0}`")]
     Comm(&'static str),
+    /// payload doesn't fit into the APDU length
+    #[error("Ledger device: APDU command too long")]
+    CommandTooLong,
+    /// no answer within the read timeout
+    #[error("Ledger device: timeout")]
+    Timeout,
+    /// exchange was cancelled with an [ExchangeCanceller](super::ExchangeCanceller)
+    #[error("Ledger device: exchange cancelled")]
+    Cancelled,
     /// i/o error
-    #[error("Ledger device: i/o error")]
+    #[error("Ledger device: i/o error: {0}")]
     Io(#[from] std::io::Error),
+    /// device was unplugged (or the USB connection reset), it has to be opened again
+    #[error("Ledger device: disconnected: {0}")]
+    Disconnected(#[source] hidapi::HidError),
     /// HID error
-    #[error("Ledger device: Io error")]
-    Hid(#[from] hidapi::HidError),
+    #[error("Ledger device: HID error: {0}")]
+    Hid(#[source] hidapi::HidError),
     /// UT8F error
     #[error("Ledger device: UTF8 error")]
     UTF8(#[from] std::str::Utf8Error),
+    /// invalid packet
+    #[error("Ledger device: framing error `{0}`")]
+    Framing(#[from] FramingError),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    #[error("incomplete header")]
+    IncompleteHeader,
+    #[error("invalid channel {0:#06x}")]
+    InvalidChannel(u16),
+    #[error("invalid tag {0:#04x}")]
+    InvalidTag(u8),
+    #[error("invalid sequence idx {got}, expected {expected}")]
+    InvalidSequence { expected: u16, got: u16 },
+    /// APDU doesn't fit into the 16bit length
+    #[error("APDU too long ({0} bytes)")]
+    TooLong(usize),
+}
+
+// hidapi reports a lost device only as an error message (or errno) of the backend
+fn is_disconnected(e: &hidapi::HidError) -> bool {
+    match e {
+        hidapi::HidError::IoError { error } => matches!(
+            error.kind(),
+            std::io::ErrorKind::NotConnected | std::io::ErrorKind::BrokenPipe
+        ),
+        hidapi::HidError::HidApiError { message } => {
+            let message = message.to_lowercase();
+            // linux: "(device disconnected)", ENODEV; windows: "The device is not connected."
+            ["disconnected", "no such device", "not connected"]
+                .iter()
+                .any(|m| message.contains(m))
+        }
+        _ => false,
+    }
+}
+
+impl From<hidapi::HidError> for LedgerHIDError {
+    fn from(e: hidapi::HidError) -> Self {
+        if is_disconnected(&e) {
+            LedgerHIDError::Disconnected(e)
+        } else {
+            LedgerHIDError::Hid(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disconnected() {
+        let error = |message: &str| {
+            LedgerHIDError::from(hidapi::HidError::HidApiError {
+                message: message.to_string(),
+            })
+        };
+        assert!(matches!(
+            error("hid_read_timeout: unexpected poll error (device disconnected)"),
+            LedgerHIDError::Disconnected(_)
+        ));
+        assert!(matches!(
+            error("No such device"),
+            LedgerHIDError::Disconnected(_)
+        ));
+        assert!(matches!(error("Permission denied"), LedgerHIDError::Hid(_)));
+        assert_eq!(
+            error("Permission denied").to_string(),
+            "Ledger device: HID error: hidapi error: Permission denied"
+        );
+    }
 }
@@ -0,0 +1,228 @@
+//! Ledger HID framing without any I/O
+//!
+//! An APDU is prefixed with its length (2 bytes, big endian) and split into 64 byte packets.
+//! Every packet starts with the channel (2 bytes), the tag `0x05` and the sequence index
+//! (2 bytes). Commands and answers are framed the same way.
+
+pub use super::errors::FramingError;
+
+pub const LEDGER_CHANNEL: u16 = 0x0101;
+pub const LEDGER_TAG_APDU: u8 = 0x05;
+pub const LEDGER_PACKET_SIZE: usize = 64;
+
+// channel, tag, sequence index
+const HEADER_SIZE: usize = 5;
+const LENGTH_SIZE: usize = 2;
+
+/// Splits an APDU into packets of [LEDGER_PACKET_SIZE] bytes (zero padded)
+///
+/// HID writes need an additional report id (`0x00`) in front of each packet.
+pub fn encode(channel: u16, apdu: &[u8]) -> Result<Vec<[u8; LEDGER_PACKET_SIZE]>, FramingError> {
+    let apdu_length = u16::try_from(apdu.len()).map_err(|_| FramingError::TooLong(apdu.len()))?;
+
+    let mut data = Vec::with_capacity(apdu.len() + LENGTH_SIZE);
+    data.extend_from_slice(&apdu_length.to_be_bytes());
+    data.extend_from_slice(apdu);
+
+    data.chunks(LEDGER_PACKET_SIZE - HEADER_SIZE)
+        .enumerate()
+        .map(|(sequence_idx, chunk)| {
+            let sequence_idx =
+                u16::try_from(sequence_idx).map_err(|_| FramingError::TooLong(apdu.len()))?;
+
+            let mut packet = [0u8; LEDGER_PACKET_SIZE];
+            packet[0..2].copy_from_slice(&channel.to_be_bytes());
+            packet[2] = LEDGER_TAG_APDU;
+            packet[3..5].copy_from_slice(&sequence_idx.to_be_bytes());
+            packet[HEADER_SIZE..HEADER_SIZE + chunk.len()].copy_from_slice(chunk);
+            Ok(packet)
+        })
+        .collect()
+}
+
+/// Reassembles an APDU from received packets
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    channel: u16,
+    sequence_idx: u16,
+    expected_length: usize,
+    apdu: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new(channel: u16) -> Self {
+        Self {
+            channel,
+            sequence_idx: 0,
+            expected_length: 0,
+            apdu: Vec::new(),
+        }
+    }
+
+    /// Adds the next packet, returns the APDU when it's complete
+    ///
+    /// After an APDU was returned (or an error) the decoder starts over with the next packet.
+    pub fn push(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>, FramingError> {
+        let result = self.decode(packet);
+        if !matches!(result, Ok(None)) {
+            self.reset();
+        }
+        result
+    }
+
+    /// Drops a partially received APDU
+    pub fn reset(&mut self) {
+        self.sequence_idx = 0;
+        self.expected_length = 0;
+        self.apdu.clear();
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Option<Vec<u8>>, FramingError> {
+        let first = self.sequence_idx == 0;
+        if packet.len() < HEADER_SIZE || (first && packet.len() < HEADER_SIZE + LENGTH_SIZE) {
+            return Err(FramingError::IncompleteHeader);
+        }
+
+        let channel = u16::from_be_bytes([packet[0], packet[1]]);
+        if channel != self.channel {
+            return Err(FramingError::InvalidChannel(channel));
+        }
+        if packet[2] != LEDGER_TAG_APDU {
+            return Err(FramingError::InvalidTag(packet[2]));
+        }
+        let sequence_idx = u16::from_be_bytes([packet[3], packet[4]]);
+        if sequence_idx != self.sequence_idx {
+            return Err(FramingError::InvalidSequence {
+                expected: self.sequence_idx,
+                got: sequence_idx,
+            });
+        }
+
+        let mut payload = &packet[HEADER_SIZE..];
+        if first {
+            self.expected_length = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+            self.apdu.reserve(self.expected_length);
+            payload = &payload[LENGTH_SIZE..];
+        }
+
+        let missing = self.expected_length - self.apdu.len();
+        self.apdu
+            .extend_from_slice(&payload[..std::cmp::min(payload.len(), missing)]);
+
+        if self.apdu.len() == self.expected_length {
+            return Ok(Some(std::mem::take(&mut self.apdu)));
+        }
+
+        self.sequence_idx = self
+            .sequence_idx
+            .checked_add(1)
+            .ok_or(FramingError::TooLong(self.expected_length))?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apdu(length: usize) -> Vec<u8> {
+        (0..length).map(|i| i as u8).collect()
+    }
+
+    fn decode(packets: &[[u8; LEDGER_PACKET_SIZE]]) -> Result<Vec<u8>, FramingError> {
+        let mut decoder = Decoder::new(LEDGER_CHANNEL);
+        let (last, rest) = packets.split_last().expect("no packets");
+        for packet in rest {
+            assert_eq!(decoder.push(packet)?, None);
+        }
+        Ok(decoder.push(last)?.expect("APDU not complete"))
+    }
+
+    fn round_trip(length: usize, packet_count: usize) {
+        let apdu = apdu(length);
+        let packets = encode(LEDGER_CHANNEL, &apdu).unwrap();
+        assert_eq!(packets.len(), packet_count, "{} bytes", length);
+        assert_eq!(decode(&packets).unwrap(), apdu);
+    }
+
+    #[test]
+    fn first_packet() {
+        let packets = encode(LEDGER_CHANNEL, &[0xe0, 0x01]).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(
+            &packets[0][..9],
+            &[0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 0x02, 0xe0, 0x01]
+        );
+        assert!(packets[0][9..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn packet_boundaries() {
+        // the first packet holds 57 bytes of the APDU, every following one 59
+        round_trip(56, 1);
+        round_trip(57, 1);
+        round_trip(58, 2);
+        round_trip(57 + 59, 2);
+        round_trip(57 + 59 + 1, 3);
+    }
+
+    #[test]
+    fn empty_apdu() {
+        round_trip(0, 1);
+    }
+
+    #[test]
+    fn maximum_length() {
+        round_trip(u16::MAX as usize, 1 + (u16::MAX as usize - 57).div_ceil(59));
+        assert_eq!(
+            encode(LEDGER_CHANNEL, &apdu(u16::MAX as usize + 1)),
+            Err(FramingError::TooLong(u16::MAX as usize + 1))
+        );
+    }
+
+    #[test]
+    fn invalid_channel() {
+        let packets = encode(0x0102, &apdu(10)).unwrap();
+        assert_eq!(decode(&packets), Err(FramingError::InvalidChannel(0x0102)));
+    }
+
+    #[test]
+    fn invalid_tag() {
+        let mut packets = encode(LEDGER_CHANNEL, &apdu(10)).unwrap();
+        packets[0][2] = 0x02;
+        assert_eq!(decode(&packets), Err(FramingError::InvalidTag(0x02)));
+    }
+
+    #[test]
+    fn invalid_sequence() {
+        let packets = encode(LEDGER_CHANNEL, &apdu(200)).unwrap();
+        let mut decoder = Decoder::new(LEDGER_CHANNEL);
+        assert_eq!(decoder.push(&packets[0]), Ok(None));
+        assert_eq!(
+            decoder.push(&packets[2]),
+            Err(FramingError::InvalidSequence {
+                expected: 1,
+                got: 2
+            })
+        );
+
+        // starts over after the error
+        assert_eq!(
+            decoder.push(&packets[1]),
+            Err(FramingError::InvalidSequence {
+                expected: 0,
+                got: 1
+            })
+        );
+        assert_eq!(decode(&packets).unwrap(), apdu(200));
+    }
+
+    #[test]
+    fn incomplete_header() {
+        let mut decoder = Decoder::new(LEDGER_CHANNEL);
+        assert_eq!(
+            decoder.push(&[0x01, 0x01, 0x05, 0x00, 0x00, 0x00]),
+            Err(FramingError::IncompleteHeader)
+        );
+    }
+}
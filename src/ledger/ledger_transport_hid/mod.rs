@@ -13,30 +13,107 @@
 *  See the License for the specific language governing permissions and
 *  limitations under the License.
 ********************************************************************************/
-// changes: removed asyncs, added additional debug messages
+// changes: removed asyncs, added additional debug messages, device selection, timeouts,
+// framing moved to `framing`
 mod errors;
-use byteorder::{BigEndian, ReadBytesExt};
+pub mod framing;
 pub use errors::LedgerHIDError;
+use framing::LEDGER_CHANNEL;
 use hidapi::{DeviceInfo, HidApi, HidDevice};
 
 use log::debug;
-use std::sync::Mutex;
-use std::{io::Cursor, ops::Deref};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, Exchange};
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding, Exchange};
 pub use hidapi;
 
 const LEDGER_VID: u16 = 0x2c97;
 const LEDGER_USAGE_PAGE: u16 = 0xFFA0;
-const LEDGER_CHANNEL: u16 = 0x0101;
 // for Windows compatability, we prepend the buffer with a 0x00
 // so the actual buffer is 64 bytes
 const LEDGER_PACKET_WRITE_SIZE: u8 = 65;
 const LEDGER_PACKET_READ_SIZE: u8 = 64;
-const LEDGER_TIMEOUT: i32 = 30_000;
+const LEDGER_DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+// reads are split into short slices to notice cancellation
+const LEDGER_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct TransportNativeHID {
     device: Mutex<HidDevice>,
+    read_timeout: Mutex<Option<Duration>>,
+    cancel: Arc<AtomicBool>,
+    // an answer may still arrive after a cancelled or timed out exchange
+    interrupted: AtomicBool,
+    encoding: Mutex<APDUEncoding>,
+}
+
+/// Cancels the exchange that is currently running on a HID transport
+///
+/// Can be cloned and moved to other threads. The device isn't told about the cancellation,
+/// e.g. it keeps showing a confirmation screen until the user reacts. An exchange that
+/// hasn't sent its command yet (e.g. waits for the device) fails without sending it.
+#[derive(Clone, Debug)]
+pub struct ExchangeCanceller {
+    cancel: Arc<AtomicBool>,
+}
+
+impl ExchangeCanceller {
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Information about an attached ledger device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerDeviceInfo {
+    /// platform specific HID path
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub product_id: u16,
+    pub interface_number: i32,
+    pub product: Option<String>,
+}
+
+// packet I/O of a device, implemented by fakes in tests
+trait HidIo {
+    fn write(&self, data: &[u8]) -> hidapi::HidResult<usize>;
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> hidapi::HidResult<usize>;
+}
+
+impl HidIo for HidDevice {
+    fn write(&self, data: &[u8]) -> hidapi::HidResult<usize> {
+        HidDevice::write(self, data)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: i32) -> hidapi::HidResult<usize> {
+        HidDevice::read_timeout(self, buf, timeout)
+    }
+}
+
+impl From<&DeviceInfo> for LedgerDeviceInfo {
+    fn from(dev: &DeviceInfo) -> Self {
+        Self {
+            path: dev.path().to_string_lossy().into_owned(),
+            serial_number: dev.serial_number().map(String::from),
+            product_id: dev.product_id(),
+            interface_number: dev.interface_number(),
+            product: dev.product_string().map(String::from),
+        }
+    }
+}
+
+/// Selects which of the attached ledger devices is opened
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum HIDSelector {
+    /// first device found
+    #[default]
+    First,
+    /// device with the given HID path (see [LedgerDeviceInfo::path])
+    Path(String),
+    /// device with the given serial number
+    Serial(String),
 }
 
 impl TransportNativeHID {
@@ -49,6 +126,13 @@ impl TransportNativeHID {
         api.device_list().filter(|dev| Self::is_ledger(dev))
     }
 
+    /// Get a list of ledger devices available with their path, serial, product id and interface
+    pub fn list_devices(api: &HidApi) -> Vec<LedgerDeviceInfo> {
+        Self::list_ledgers(api)
+            .map(LedgerDeviceInfo::from)
+            .collect()
+    }
+
     /// Create a new HID transport, connecting to the first ledger found
     /// # Warning
     /// Opening the same device concurrently will lead to device lock after the first handle is closed
@@ -62,6 +146,39 @@ impl TransportNativeHID {
         Self::open_device(api, first_ledger)
     }
 
+    /// Find the device chosen by `selector` without opening it
+    pub fn find_device<'a>(
+        api: &'a HidApi,
+        selector: &HIDSelector,
+    ) -> Result<&'a DeviceInfo, LedgerHIDError> {
+        let mut ledgers = Self::list_ledgers(api);
+        match selector {
+            HIDSelector::First => ledgers.next(),
+            HIDSelector::Path(path) => ledgers.find(|dev| dev.path().to_string_lossy() == *path),
+            HIDSelector::Serial(serial_number) => {
+                ledgers.find(|dev| dev.serial_number() == Some(serial_number.as_str()))
+            }
+        }
+        .ok_or(LedgerHIDError::DeviceNotFound)
+    }
+
+    /// Create a new HID transport, connecting to the ledger with the given HID path
+    pub fn open_by_path(api: &HidApi, path: &str) -> Result<Self, LedgerHIDError> {
+        debug!("new HID transport for path {}", path);
+        Self::open_selected(api, &HIDSelector::Path(String::from(path)))
+    }
+
+    /// Create a new HID transport, connecting to the ledger with the given serial number
+    pub fn open_by_serial(api: &HidApi, serial_number: &str) -> Result<Self, LedgerHIDError> {
+        debug!("new HID transport for serial {}", serial_number);
+        Self::open_selected(api, &HIDSelector::Serial(String::from(serial_number)))
+    }
+
+    /// Create a new HID transport for the device chosen by `selector`
+    pub fn open_selected(api: &HidApi, selector: &HIDSelector) -> Result<Self, LedgerHIDError> {
+        Self::open_device(api, Self::find_device(api, selector)?)
+    }
+
     /// Open a specific ledger device
     ///
     /// # Note
@@ -72,41 +189,61 @@ impl TransportNativeHID {
     /// see [issue](https://github.com/ruabmbua/hidapi-rs/issues/81)
     pub fn open_device(api: &HidApi, device: &DeviceInfo) -> Result<Self, LedgerHIDError> {
         debug!("open device");
-        let device = device.open_device(api)?;
+        let device = device
+            .open_device(api)
+            .map_err(|source| LedgerHIDError::OpenFailed {
+                path: device.path().to_string_lossy().into_owned(),
+                source,
+            })?;
         let _ = device.set_blocking_mode(true);
 
         let ledger = TransportNativeHID {
             device: Mutex::new(device),
+            read_timeout: Mutex::new(Some(LEDGER_DEFAULT_TIMEOUT)),
+            cancel: Arc::new(AtomicBool::new(false)),
+            interrupted: AtomicBool::new(false),
+            encoding: Mutex::new(APDUEncoding::Short),
         };
 
         Ok(ledger)
     }
 
+    /// Sets how long exchanges wait for an answer (default 30s), `None` waits forever
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().expect("HID timeout poisoned") = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock().expect("HID timeout poisoned")
+    }
+
+    /// Sets how commands are serialized (see [APDUEncoding])
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        *self.encoding.lock().expect("HID encoding poisoned") = encoding;
+    }
+
+    pub fn apdu_encoding(&self) -> APDUEncoding {
+        *self.encoding.lock().expect("HID encoding poisoned")
+    }
+
+    /// Handle to cancel exchanges of this transport from another thread
+    pub fn canceller(&self) -> ExchangeCanceller {
+        ExchangeCanceller {
+            cancel: self.cancel.clone(),
+        }
+    }
+
     fn write_apdu(
-        device: &HidDevice,
+        device: &impl HidIo,
         channel: u16,
         apdu_command: &[u8],
     ) -> Result<i32, LedgerHIDError> {
-        let command_length = apdu_command.len();
-        let mut in_data = Vec::with_capacity(command_length + 2);
-        in_data.push(((command_length >> 8) & 0xFF) as u8);
-        in_data.push((command_length & 0xFF) as u8);
-        in_data.extend_from_slice(apdu_command);
-
         let mut buffer = vec![0u8; LEDGER_PACKET_WRITE_SIZE as usize];
         // Windows platform requires 0x00 prefix and Linux/Mac tolerate this as well
         buffer[0] = 0x00;
-        buffer[1] = ((channel >> 8) & 0xFF) as u8; // channel big endian
-        buffer[2] = (channel & 0xFF) as u8; // channel big endian
-        buffer[3] = 0x05u8;
 
-        for (sequence_idx, chunk) in in_data
-            .chunks((LEDGER_PACKET_WRITE_SIZE - 6) as usize)
-            .enumerate()
-        {
-            buffer[4] = ((sequence_idx >> 8) & 0xFF) as u8; // sequence_idx big endian
-            buffer[5] = (sequence_idx & 0xFF) as u8; // sequence_idx big endian
-            buffer[6..6 + chunk.len()].copy_from_slice(chunk);
+        for packet in framing::encode(channel, apdu_command)? {
+            buffer[1..].copy_from_slice(&packet);
 
             debug!("[{:3}] >> {:}", buffer.len(), hex::encode(&buffer));
 
@@ -122,66 +259,73 @@ impl TransportNativeHID {
                         ));
                     }
                 }
-                Err(x) => return Err(LedgerHIDError::Hid(x)),
+                Err(x) => return Err(x.into()),
             }
         }
         Ok(1)
     }
 
+    // waits for the next packet until the deadline is reached or the exchange is cancelled
+    fn read_packet(
+        device: &impl HidIo,
+        buffer: &mut [u8],
+        deadline: Option<Instant>,
+        cancel: &AtomicBool,
+    ) -> Result<usize, LedgerHIDError> {
+        loop {
+            if cancel.swap(false, Ordering::SeqCst) {
+                return Err(LedgerHIDError::Cancelled);
+            }
+
+            let slice = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(LedgerHIDError::Timeout);
+                    }
+                    std::cmp::min(deadline - now, LEDGER_POLL_INTERVAL)
+                }
+                None => LEDGER_POLL_INTERVAL,
+            };
+
+            // round up, a timeout of 0 doesn't block at all
+            let res = device.read_timeout(buffer, slice.as_millis().max(1) as i32)?;
+            if res > 0 {
+                return Ok(res);
+            }
+        }
+    }
+
+    // drops packets of answers that arrived after their exchange was given up
+    fn drain(device: &impl HidIo) -> Result<(), LedgerHIDError> {
+        let mut buffer = vec![0u8; LEDGER_PACKET_READ_SIZE as usize];
+        while device.read_timeout(&mut buffer, 0)? > 0 {
+            debug!("dropped stale packet");
+        }
+        Ok(())
+    }
+
     fn read_apdu(
-        device: &HidDevice,
+        device: &impl HidIo,
         channel: u16,
         apdu_answer: &mut Vec<u8>,
+        deadline: Option<Instant>,
+        cancel: &AtomicBool,
     ) -> Result<usize, LedgerHIDError> {
         let mut buffer = vec![0u8; LEDGER_PACKET_READ_SIZE as usize];
-        let mut sequence_idx = 0u16;
-        let mut expected_apdu_len = 0usize;
+        let mut decoder = framing::Decoder::new(channel);
         //debug!("read_apdu enter loop");
         loop {
             //debug!("read_apdu waiting for data");
-            let res = device.read_timeout(&mut buffer, LEDGER_TIMEOUT)?;
+            let res = Self::read_packet(device, &mut buffer, deadline, cancel)?;
             //debug!("read_apdu received data: {}", res);
 
-            if (sequence_idx == 0 && res < 7) || res < 5 {
-                return Err(LedgerHIDError::Comm("Read error. Incomplete header"));
-            }
+            debug!("[{:3}] << {:}", res, hex::encode(&buffer[..res]));
 
-            let mut rdr = Cursor::new(&buffer);
-
-            let rcv_channel = rdr.read_u16::<BigEndian>()?;
-            let rcv_tag = rdr.read_u8()?;
-            let rcv_seq_idx = rdr.read_u16::<BigEndian>()?;
-
-            if rcv_channel != channel {
-                return Err(LedgerHIDError::Comm("Invalid channel"));
-            }
-            if rcv_tag != 0x05u8 {
-                return Err(LedgerHIDError::Comm("Invalid tag"));
-            }
-
-            if rcv_seq_idx != sequence_idx {
-                return Err(LedgerHIDError::Comm("Invalid sequence idx"));
-            }
-
-            if rcv_seq_idx == 0 {
-                expected_apdu_len = rdr.read_u16::<BigEndian>()? as usize;
-            }
-
-            let available: usize = buffer.len() - rdr.position() as usize;
-            let missing: usize = expected_apdu_len - apdu_answer.len();
-            let end_p = rdr.position() as usize + std::cmp::min(available, missing);
-
-            let new_chunk = &buffer[rdr.position() as usize..end_p];
-
-            debug!("[{:3}] << {:}", new_chunk.len(), hex::encode(new_chunk));
-
-            apdu_answer.extend_from_slice(new_chunk);
-
-            if apdu_answer.len() >= expected_apdu_len {
+            if let Some(answer) = decoder.push(&buffer[..res])? {
+                *apdu_answer = answer;
                 return Ok(apdu_answer.len());
             }
-
-            sequence_idx += 1;
         }
     }
 
@@ -189,16 +333,60 @@ impl TransportNativeHID {
         &self,
         command: &APDUCommand<I>,
     ) -> Result<APDUAnswer<Vec<u8>>, LedgerHIDError> {
+        self.exchange_with_timeout(command, self.read_timeout())
+    }
+
+    /// Like [TransportNativeHID::exchange] with a read timeout for this call only
+    pub fn exchange_with_timeout<I: Deref<Target = [u8]>>(
+        &self,
+        command: &APDUCommand<I>,
+        timeout: Option<Duration>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHIDError> {
+        let raw_command = command
+            .serialize_with(self.apdu_encoding())
+            .map_err(|_| LedgerHIDError::CommandTooLong)?;
+
         let device = self.device.lock().expect("HID device poisoned");
+        let res = Self::exchange_device(
+            &*device,
+            &raw_command,
+            timeout,
+            &self.cancel,
+            &self.interrupted,
+        );
+        // a cancel request that came too late doesn't affect the next exchange
+        self.cancel.store(false, Ordering::SeqCst);
+        res
+    }
 
-        if let Err(e) = Self::write_apdu(&device, LEDGER_CHANNEL, &command.serialize()) {
+    fn exchange_device(
+        device: &impl HidIo,
+        raw_command: &[u8],
+        timeout: Option<Duration>,
+        cancel: &AtomicBool,
+        interrupted: &AtomicBool,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHIDError> {
+        // cancelled before the command was sent (e.g. while waiting for the device),
+        // the device must not run it
+        if cancel.swap(false, Ordering::SeqCst) {
+            return Err(LedgerHIDError::Cancelled);
+        }
+
+        if interrupted.swap(false, Ordering::SeqCst) {
+            Self::drain(device)?;
+        }
+
+        if let Err(e) = Self::write_apdu(device, LEDGER_CHANNEL, raw_command) {
             debug!("Error in write_apdu: {:?}", e);
             return Err(e);
         }
 
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
         let mut answer: Vec<u8> = Vec::with_capacity(256);
-        if let Err(e) = Self::read_apdu(&device, LEDGER_CHANNEL, &mut answer) {
+        if let Err(e) = Self::read_apdu(device, LEDGER_CHANNEL, &mut answer, deadline, cancel) {
             debug!("Error in read_apdu: {:?}", e);
+            // the rest of the answer may still arrive, drop it before the next exchange
+            interrupted.store(true, Ordering::SeqCst);
             return Err(e);
         }
 
@@ -227,4 +415,137 @@ impl Exchange for TransportNativeHID {
     {
         self.exchange(command)
     }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.apdu_encoding()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    // records written packets and answers reads from a queue of packets
+    #[derive(Default)]
+    struct FakeDevice<'a> {
+        written: RefCell<Vec<Vec<u8>>>,
+        packets: RefCell<VecDeque<[u8; framing::LEDGER_PACKET_SIZE]>>,
+        // set when a read finds no packet
+        cancel_on_idle: Option<&'a AtomicBool>,
+    }
+
+    impl FakeDevice<'_> {
+        fn answer(&self, apdu: &[u8]) {
+            self.packets
+                .borrow_mut()
+                .extend(framing::encode(LEDGER_CHANNEL, apdu).unwrap());
+        }
+
+        // command reassembled from the written packets (without report id)
+        fn command(&self) -> Option<Vec<u8>> {
+            let mut decoder = framing::Decoder::new(LEDGER_CHANNEL);
+            let written = self.written.borrow();
+            written
+                .iter()
+                .map(|packet| {
+                    assert_eq!(packet[0], 0x00);
+                    decoder.push(&packet[1..]).unwrap()
+                })
+                .last()
+                .flatten()
+        }
+    }
+
+    impl HidIo for FakeDevice<'_> {
+        fn write(&self, data: &[u8]) -> hidapi::HidResult<usize> {
+            self.written.borrow_mut().push(data.to_vec());
+            Ok(data.len())
+        }
+
+        fn read_timeout(&self, buf: &mut [u8], _timeout: i32) -> hidapi::HidResult<usize> {
+            match self.packets.borrow_mut().pop_front() {
+                Some(packet) => {
+                    buf[..packet.len()].copy_from_slice(&packet);
+                    Ok(packet.len())
+                }
+                None => {
+                    if let Some(cancel) = self.cancel_on_idle {
+                        cancel.store(true, Ordering::SeqCst);
+                    }
+                    Ok(0)
+                }
+            }
+        }
+    }
+
+    const COMMAND: [u8; 7] = [0x7b, 0x10, 0x00, 0x00, 0x02, 0xaa, 0xbb];
+
+    fn exchange(
+        device: &FakeDevice,
+        cancel: &AtomicBool,
+        interrupted: &AtomicBool,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHIDError> {
+        TransportNativeHID::exchange_device(
+            device,
+            &COMMAND,
+            Some(Duration::from_secs(1)),
+            cancel,
+            interrupted,
+        )
+    }
+
+    #[test]
+    fn exchange_device() {
+        let device = FakeDevice::default();
+        device.answer(&[0x01, 0x02, 0x90, 0x00]);
+
+        let answer = exchange(&device, &AtomicBool::new(false), &AtomicBool::new(false)).unwrap();
+        assert_eq!(answer.data(), &[0x01, 0x02]);
+        assert_eq!(answer.retcode(), 0x9000);
+        assert_eq!(device.command().unwrap(), COMMAND);
+    }
+
+    #[test]
+    fn cancelled_before_write() {
+        let device = FakeDevice::default();
+        device.answer(&[0x90, 0x00]);
+        let cancel = AtomicBool::new(true);
+        let interrupted = AtomicBool::new(false);
+
+        assert!(matches!(
+            exchange(&device, &cancel, &interrupted),
+            Err(LedgerHIDError::Cancelled)
+        ));
+        // nothing was sent, nothing has to be drained
+        assert!(device.written.borrow().is_empty());
+        assert!(!interrupted.load(Ordering::SeqCst));
+
+        // the cancellation only applies once
+        assert_eq!(
+            exchange(&device, &cancel, &interrupted).unwrap().retcode(),
+            0x9000
+        );
+        assert_eq!(device.command().unwrap(), COMMAND);
+    }
+
+    #[test]
+    fn cancelled_while_reading() {
+        let cancel = AtomicBool::new(false);
+        let interrupted = AtomicBool::new(false);
+        let device = FakeDevice {
+            cancel_on_idle: Some(&cancel),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            exchange(&device, &cancel, &interrupted),
+            Err(LedgerHIDError::Cancelled)
+        ));
+        // the command was sent before the exchange was given up
+        assert_eq!(device.command().unwrap(), COMMAND);
+        assert!(interrupted.load(Ordering::SeqCst));
+    }
 }
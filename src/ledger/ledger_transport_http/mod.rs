@@ -0,0 +1,208 @@
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding, Exchange};
+
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::transport::errors::LedgerHTTPError;
+use crate::transport::HTTPConfig;
+
+#[cfg(test)]
+pub(crate) mod stand_in;
+
+/// Transport for the REST API of Speculos (`POST /apdu`)
+pub struct TransportHTTP {
+    url: String,
+    agent: ureq::Agent,
+    read_timeout: Mutex<Option<Duration>>,
+    encoding: Mutex<APDUEncoding>,
+}
+
+impl TransportHTTP {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self::from_config(&HTTPConfig::new(host, port))
+    }
+
+    pub fn from_config(config: &HTTPConfig) -> Self {
+        Self {
+            url: format!("{}/apdu", config.base_url()),
+            // the read timeout is set per request
+            agent: HTTPConfig {
+                read_timeout: None,
+                ..config.clone()
+            }
+            .agent(),
+            read_timeout: Mutex::new(config.read_timeout),
+            encoding: Mutex::new(APDUEncoding::Short),
+        }
+    }
+
+    /// Sets how long exchanges wait for an answer, `None` waits forever
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().expect("HTTP timeout poisoned") = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock().expect("HTTP timeout poisoned")
+    }
+
+    /// Sets how commands are serialized (see [APDUEncoding])
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        *self.encoding.lock().expect("HTTP encoding poisoned") = encoding;
+    }
+
+    pub fn apdu_encoding(&self) -> APDUEncoding {
+        *self.encoding.lock().expect("HTTP encoding poisoned")
+    }
+
+    fn request(
+        &self,
+        raw_command: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, LedgerHTTPError> {
+        let mut request = self.agent.post(&self.url);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let response = request
+            .send_json(serde_json::json!({ "data": hex::encode(raw_command) }))
+            .map_err(LedgerHTTPError::from)?;
+
+        let body: serde_json::Value = response
+            .into_json()
+            .map_err(|_| LedgerHTTPError::ResponseError)?;
+
+        // answer contains the data and the return code
+        let data = body["data"]
+            .as_str()
+            .ok_or(LedgerHTTPError::ResponseError)?;
+        hex::decode(data).map_err(|_| LedgerHTTPError::ResponseError)
+    }
+
+    pub fn exchange<I: Deref<Target = [u8]>>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHTTPError> {
+        self.exchange_with_timeout(command, self.read_timeout())
+    }
+
+    /// Like [TransportHTTP::exchange] with a read timeout for this call only
+    pub fn exchange_with_timeout<I: Deref<Target = [u8]>>(
+        &self,
+        command: &APDUCommand<I>,
+        timeout: Option<Duration>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerHTTPError> {
+        let raw_command = command
+            .serialize_with(self.apdu_encoding())
+            .map_err(|_| LedgerHTTPError::CommandTooLong)?;
+        let raw_answer = self.request(&raw_command, timeout)?;
+        APDUAnswer::from_answer(raw_answer).map_err(|_| LedgerHTTPError::ResponseError)
+    }
+}
+
+impl Exchange for TransportHTTP {
+    type Error = LedgerHTTPError;
+    type AnswerType = Vec<u8>;
+
+    fn exchange<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.exchange(command)
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.apdu_encoding()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use stand_in::Response;
+
+    fn command() -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: 0x7b,
+            ins: 0x10,
+            p1: 0,
+            p2: 0,
+            data: vec![0x01, 0x02],
+        }
+    }
+
+    fn serve_once(
+        response: Response,
+    ) -> (TransportHTTP, std::sync::mpsc::Receiver<stand_in::Request>) {
+        let (port, requests) = stand_in::serve(vec![response]);
+        (TransportHTTP::new("127.0.0.1", port), requests)
+    }
+
+    #[test]
+    fn apdu() {
+        let (transport, requests) = serve_once(Response::ok(r#"{"data": "aa9000"}"#));
+        let answer = transport.exchange(&command()).unwrap();
+        assert_eq!(answer.data(), &[0xaa]);
+        assert_eq!(answer.retcode(), 0x9000);
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/apdu");
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body, serde_json::json!({ "data": "7b100000020102" }));
+    }
+
+    #[test]
+    fn invalid_body() {
+        let (transport, _requests) = serve_once(Response::ok(r#"{"error": "x"}"#));
+        assert!(matches!(
+            transport.exchange(&command()),
+            Err(LedgerHTTPError::ResponseError)
+        ));
+    }
+
+    #[test]
+    fn status_error() {
+        let (transport, _requests) = serve_once(Response {
+            status: "500 Internal Server Error",
+            ..Response::ok("{}")
+        });
+        assert!(matches!(
+            transport.exchange(&command()),
+            Err(LedgerHTTPError::StatusError(500))
+        ));
+    }
+
+    #[test]
+    fn timeout() {
+        let (transport, _requests) = serve_once(Response {
+            delay: Duration::from_secs(2),
+            ..Response::ok(r#"{"data": "9000"}"#)
+        });
+        assert!(matches!(
+            transport.exchange_with_timeout(&command(), Some(Duration::from_millis(100))),
+            Err(LedgerHTTPError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn connect_error() {
+        // nothing listens on the port once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let transport = TransportHTTP::new("127.0.0.1", port);
+        match transport.exchange(&command()) {
+            Err(e @ LedgerHTTPError::ConnectError(_)) => {
+                assert!(std::error::Error::source(&e).is_some())
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
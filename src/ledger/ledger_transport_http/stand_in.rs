@@ -0,0 +1,85 @@
+//! Minimal HTTP server standing in for the Speculos REST API in tests
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Request as received by the stand-in
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// path including the query
+    pub path: String,
+    pub body: String,
+}
+
+/// Canned response
+pub struct Response {
+    pub status: &'static str,
+    pub body: String,
+    /// wait before answering
+    pub delay: Duration,
+}
+
+impl Response {
+    pub fn ok(body: &str) -> Self {
+        Self {
+            status: "200 OK",
+            body: String::from(body),
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+/// Answers one connection per response (in order), returns the port and the received requests
+pub fn serve(responses: Vec<Response>) -> (u16, mpsc::Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let _ = tx.send(Request {
+                method,
+                path,
+                body: String::from_utf8(body).unwrap(),
+            });
+
+            thread::sleep(response.delay);
+            let raw_response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.status,
+                response.body.len(),
+                response.body
+            );
+            // the client may have given up already
+            let _ = reader.get_mut().write_all(raw_response.as_bytes());
+        }
+    });
+    (port, rx)
+}
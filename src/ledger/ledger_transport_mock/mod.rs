@@ -0,0 +1,487 @@
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, Exchange};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use crate::transport::errors::LedgerMockError;
+
+pub type MockHandler =
+    Box<dyn FnMut(&APDUCommand<Vec<u8>>) -> Result<APDUAnswer<Vec<u8>>, LedgerMockError> + Send>;
+
+enum Expectation {
+    Command {
+        command: APDUCommand<Vec<u8>>,
+        answer: APDUAnswer<Vec<u8>>,
+    },
+    Handler(MockHandler),
+}
+
+#[derive(Default)]
+struct MockState {
+    expectations: VecDeque<Expectation>,
+    // answers everything that isn't scripted
+    fallback: Option<MockHandler>,
+    // number of answered commands
+    exchanged: usize,
+    unexpected: Vec<LedgerMockError>,
+}
+
+/// In-process transport that answers with scripted responses
+///
+/// Expectations are consumed in the order they were added. Clones share the script, so a
+/// clone can be handed to [crate::transport::create_transport] (`TransportTypes::Mock`)
+/// and checked with [TransportMock::verify] afterwards.
+#[derive(Clone, Default)]
+pub struct TransportMock {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl fmt::Debug for TransportMock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("TransportMock")
+            .field("pending", &state.expectations.len())
+            .field("unexpected", &state.unexpected)
+            .finish()
+    }
+}
+
+// two mocks are the same if they share the script
+impl PartialEq for TransportMock {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for TransportMock {}
+
+fn command_to_hex(command: &APDUCommand<Vec<u8>>) -> String {
+    hex::encode(command.serialize())
+}
+
+fn matches(a: &APDUCommand<Vec<u8>>, b: &APDUCommand<Vec<u8>>) -> bool {
+    a.cla == b.cla && a.ins == b.ins && a.p1 == b.p1 && a.p2 == b.p2 && a.data == b.data
+}
+
+impl TransportMock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().expect("mock state poisoned")
+    }
+
+    /// Expect exactly `command` next and answer with `answer`
+    pub fn expect(&self, command: APDUCommand<Vec<u8>>, answer: APDUAnswer<Vec<u8>>) -> &Self {
+        self.state()
+            .expectations
+            .push_back(Expectation::Command { command, answer });
+        self
+    }
+
+    /// Expect `command` next and answer with `data` and the return code 0x9000
+    pub fn expect_ok(&self, command: APDUCommand<Vec<u8>>, data: &[u8]) -> &Self {
+        let mut answer = data.to_vec();
+        answer.extend_from_slice(&0x9000u16.to_be_bytes());
+        self.expect(
+            command,
+            APDUAnswer::from_answer(answer).expect("answer too short"),
+        )
+    }
+
+    /// Expect `command` next and answer with the return code `retcode` only
+    pub fn expect_retcode(&self, command: APDUCommand<Vec<u8>>, retcode: u16) -> &Self {
+        self.expect(
+            command,
+            APDUAnswer::from_answer(retcode.to_be_bytes().to_vec()).expect("answer too short"),
+        )
+    }
+
+    /// Let a closure answer the next command
+    ///
+    /// The closure can also return an error to simulate a failing transport.
+    pub fn expect_with<F>(&self, handler: F) -> &Self
+    where
+        F: FnMut(&APDUCommand<Vec<u8>>) -> Result<APDUAnswer<Vec<u8>>, LedgerMockError>
+            + Send
+            + 'static,
+    {
+        self.state()
+            .expectations
+            .push_back(Expectation::Handler(Box::new(handler)));
+        self
+    }
+
+    /// Let a closure answer all commands when no scripted expectation is left
+    pub fn set_fallback<F>(&self, handler: F) -> &Self
+    where
+        F: FnMut(&APDUCommand<Vec<u8>>) -> Result<APDUAnswer<Vec<u8>>, LedgerMockError>
+            + Send
+            + 'static,
+    {
+        self.state().fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// Checks that there were no unexpected commands and all expectations were consumed
+    pub fn verify(&self) -> Result<(), LedgerMockError> {
+        let state = self.state();
+        if let Some(e) = state.unexpected.first() {
+            return Err(e.clone());
+        }
+        if !state.expectations.is_empty() {
+            return Err(LedgerMockError::Unconsumed(state.expectations.len()));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn lock_key(&self) -> String {
+        format!("mock://{:p}", Arc::as_ptr(&self.state))
+    }
+
+    pub fn exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerMockError> {
+        let mut state = self.state();
+
+        let answer = match state.expectations.pop_front() {
+            Some(Expectation::Command {
+                command: expected,
+                answer,
+            }) => {
+                if !matches(&expected, command) {
+                    let e = LedgerMockError::Mismatch {
+                        index: state.exchanged,
+                        expected: command_to_hex(&expected),
+                        got: command_to_hex(command),
+                    };
+                    log::error!("{}", e);
+                    state.unexpected.push(e.clone());
+                    // keep it for the next exchange
+                    state.expectations.push_front(Expectation::Command {
+                        command: expected,
+                        answer,
+                    });
+                    return Err(e);
+                }
+                answer
+            }
+            Some(Expectation::Handler(mut handler)) => handler(command)?,
+            None => match state.fallback.as_mut() {
+                Some(handler) => handler(command)?,
+                None => {
+                    let e = LedgerMockError::UnexpectedCommand(command_to_hex(command));
+                    log::error!("{}", e);
+                    state.unexpected.push(e.clone());
+                    return Err(e);
+                }
+            },
+        };
+
+        state.exchanged += 1;
+
+        Ok(answer)
+    }
+}
+
+impl Exchange for TransportMock {
+    type Error = LedgerMockError;
+    type AnswerType = Vec<u8>;
+
+    fn exchange<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        let command = APDUCommand {
+            cla: command.cla,
+            ins: command.ins,
+            p1: command.p1,
+            p2: command.p2,
+            data: command.data.to_vec(),
+        };
+        self.exchange(&command)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::api::{self, constants, errors::APIError, sign::Unlock};
+    use crate::validate::tests::Essence;
+
+    // answers of the commands sent by `get_ledger_by_transport` (IOTA app 1.0.0)
+    fn ledger(mock: &TransportMock) -> Box<crate::LedgerHardwareWallet<TransportMock>> {
+        let app_config = [1, 0, 0, 0, 0, 0];
+        mock.expect_ok(api::reset::command(), &[])
+            .expect_ok(api::get_app_config::command(), &app_config)
+            .expect_ok(api::get_data_buffer_state::command(), &[0, 0, 0, 251, 8])
+            .expect_ok(api::get_app_config::command(), &app_config)
+            .expect_ok(
+                api::set_account::command(constants::AppModes::ModeIOTAStardust, 0x80000000),
+                &[],
+            );
+        crate::get_ledger_by_transport(0x107a, 0x80000000, mock.clone()).unwrap()
+    }
+
+    #[test]
+    fn is_locked() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        mock.expect_retcode(api::get_data_buffer_state::command(), 0x6982)
+            .expect_retcode(api::get_data_buffer_state::command(), 0x6982);
+
+        assert!(matches!(
+            api::get_data_buffer_state::exec(&mock),
+            Err(APIError::SecurityStatusNotSatisfied)
+        ));
+        assert!(ledger.is_locked().unwrap());
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn user_confirm_rejected() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        mock.expect_retcode(api::user_confirm::command(), 0x6985);
+
+        assert!(matches!(
+            ledger.user_confirm(),
+            Err(APIError::ConditionsOfUseNotSatisfied)
+        ));
+        mock.verify().unwrap();
+    }
+
+    pub(crate) fn key(index: u32) -> crate::LedgerBIP32Index {
+        crate::LedgerBIP32Index {
+            bip32_index: constants::HARDENED | index,
+            bip32_change: constants::HARDENED,
+        }
+    }
+
+    // buffer of 8 blocks, `data_type` and `data_length` as given
+    fn buffer_state(data_type: u8, data_length: u16) -> Vec<u8> {
+        let mut state = data_length.to_le_bytes().to_vec();
+        state.extend_from_slice(&[data_type, constants::DATA_BLOCK_SIZE as u8, 8]);
+        state
+    }
+
+    // upload of the essence followed by the key paths, then `prepare_signing`
+    // (also used by the async tests)
+    pub(crate) fn expect_prepare_signing(
+        mock: &TransportMock,
+        essence: &[u8],
+        keys: &[crate::LedgerBIP32Index],
+        remainder: Option<crate::LedgerRemainder>,
+    ) {
+        let mut buffer = essence.to_vec();
+        for key in keys {
+            buffer.extend_from_slice(&key.bip32_index.to_le_bytes());
+            buffer.extend_from_slice(&key.bip32_change.to_le_bytes());
+        }
+
+        mock.expect_ok(api::clear_data_buffer::command(), &[])
+            .expect_ok(api::get_data_buffer_state::command(), &buffer_state(0, 0));
+        for (block, chunk) in buffer.chunks(constants::DATA_BLOCK_SIZE).enumerate() {
+            let mut data = chunk.to_vec();
+            data.resize(constants::DATA_BLOCK_SIZE, 0);
+            mock.expect_ok(api::write_data_block::command(block as u8, data), &[]);
+        }
+        let remainder_output = remainder.unwrap_or_default();
+        mock.expect_ok(
+            api::prepare_signing::command(
+                remainder.is_some(),
+                remainder_output.output_index,
+                remainder_output.bip32,
+            ),
+            &[],
+        )
+        .expect_ok(
+            api::get_data_buffer_state::command(),
+            &buffer_state(
+                constants::DataTypeEnum::ValidatedEssence as u8,
+                buffer.len() as u16,
+            ),
+        );
+    }
+
+    pub(crate) fn signature_unlock() -> Unlock {
+        Unlock::Signature {
+            public_key: [0x11; 32],
+            signature: [0x22; 64],
+        }
+    }
+
+    #[test]
+    fn sign_essence() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        // two inputs, the second one is unlocked by a reference to the first
+        let essence = Essence::new(2, 2).to_bytes();
+        let keys = [key(0), key(1)];
+        let remainder = crate::LedgerRemainder {
+            output_index: 1,
+            bip32: key(2),
+        };
+        expect_prepare_signing(&mock, &essence, &keys, Some(remainder));
+        mock.expect_ok(api::user_confirm::command(), &[])
+            .expect_ok(api::sign::command(0), &signature_unlock().to_bytes())
+            .expect_ok(api::sign::command(1), &[1, 0, 0]);
+
+        assert_eq!(
+            ledger
+                .sign_essence(essence, keys.to_vec(), Some(remainder))
+                .unwrap(),
+            vec![
+                crate::LedgerSignedInput {
+                    input_index: 0,
+                    bip32: key(0),
+                    unlock: signature_unlock(),
+                },
+                crate::LedgerSignedInput {
+                    input_index: 1,
+                    bip32: key(1),
+                    unlock: Unlock::Reference { index: 0 },
+                },
+            ]
+        );
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn sign_essence_rejected() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        let essence = Essence::new(1, 1).to_bytes();
+        expect_prepare_signing(&mock, &essence, &[key(0)], None);
+        mock.expect_retcode(api::user_confirm::command(), 0x6985)
+            .expect_ok(api::clear_data_buffer::command(), &[]);
+
+        assert!(matches!(
+            ledger.sign_essence(essence, vec![key(0)], None),
+            Err(APIError::ConditionsOfUseNotSatisfied)
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn sign_essence_sign_failed() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        let essence = Essence::new(2, 1).to_bytes();
+        expect_prepare_signing(&mock, &essence, &[key(0), key(1)], None);
+        mock.expect_ok(api::user_confirm::command(), &[])
+            .expect_ok(api::sign::command(0), &signature_unlock().to_bytes())
+            .expect_retcode(api::sign::command(1), 0x6985)
+            .expect_ok(api::clear_data_buffer::command(), &[]);
+
+        assert!(matches!(
+            ledger.sign_essence(essence, vec![key(0), key(1)], None),
+            Err(APIError::ConditionsOfUseNotSatisfied)
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn sign() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        mock.expect_ok(api::sign::command(0), &signature_unlock().to_bytes())
+            .expect_ok(api::sign::command(1), &[1, 0, 0]);
+
+        assert_eq!(
+            ledger.sign(2).unwrap(),
+            vec![signature_unlock(), Unlock::Reference { index: 0 }]
+        );
+        // signatures are addressed with an u8 index
+        assert!(matches!(
+            ledger.sign(257),
+            Err(APIError::CommandInvalidData)
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn prepare_blind_signing_essence() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        let essence_hash =
+            hex::decode("11c0e79b71c3976ccd0c02d1310e2516c08edc9d8b6f57ccd680d63a4d8e72da")
+                .unwrap();
+
+        // hash, number of keys (u16) and the keys (index and change, u32 each)
+        let mut block = essence_hash.clone();
+        block.extend_from_slice(&[2, 0]);
+        block.extend_from_slice(&[0, 0, 0, 0x80, 0, 0, 0, 0x80]);
+        block.extend_from_slice(&[1, 0, 0, 0x80, 0, 0, 0, 0x80]);
+        let buffer_len = block.len() as u16;
+        block.resize(constants::DATA_BLOCK_SIZE, 0);
+        mock.expect_ok(api::clear_data_buffer::command(), &[])
+            .expect_ok(api::get_data_buffer_state::command(), &buffer_state(0, 0))
+            .expect_ok(api::write_data_block::command(0, block), &[])
+            .expect_ok(api::prepare_blind_signing::command(), &[])
+            .expect_ok(
+                api::get_data_buffer_state::command(),
+                &buffer_state(constants::DataTypeEnum::ValidatedEssence as u8, buffer_len),
+            );
+
+        assert_eq!(
+            ledger
+                .prepare_blind_signing_essence(vec![key(0), key(1)], &[1, 2, 3])
+                .unwrap()
+                .to_vec(),
+            essence_hash
+        );
+
+        // only 32 byte hashes are uploaded
+        assert!(matches!(
+            ledger.prepare_blind_signing(vec![key(0)], vec![0; 31]),
+            Err(APIError::CommandInvalidData)
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn invalid_essence_not_uploaded() {
+        let mock = TransportMock::new();
+        let ledger = ledger(&mock);
+        let input = crate::LedgerBIP32Index {
+            bip32_index: 0x80000000,
+            bip32_change: 0x80000000,
+        };
+
+        // no commands are expected after the init
+        assert!(matches!(
+            ledger.sign_essence(vec![2; 64], vec![input], None),
+            Err(APIError::Essence(_))
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn unexpected_command() {
+        let mock = TransportMock::new();
+        mock.expect_ok(api::reset::command(), &[]);
+
+        assert!(matches!(
+            api::user_confirm::exec(&mock),
+            Err(APIError::Mock(LedgerMockError::Mismatch { index: 0, .. }))
+        ));
+        assert!(matches!(
+            mock.verify(),
+            Err(LedgerMockError::Mismatch { .. })
+        ));
+        api::reset::exec(&mock).unwrap();
+        assert!(matches!(
+            api::reset::exec(&mock),
+            Err(APIError::Mock(LedgerMockError::UnexpectedCommand(_)))
+        ));
+    }
+}
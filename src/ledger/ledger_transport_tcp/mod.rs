@@ -1,67 +1,375 @@
-use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand};
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding, Exchange};
 
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::Duration;
 
 use crate::transport::errors::LedgerTCPError;
+use crate::transport::{RetryPolicy, TCPConfig};
 
-pub type Callback = fn(apdu_command: &APDUCommand<Vec<u8>>, apdu_answer: &APDUAnswer<Vec<u8>>);
+// extended length answer (Le = 0x0000), without the status word
+const MAX_ANSWER_DATA_LENGTH: u32 = 0x10000;
 
 pub struct TransportTCP {
     url: String,
-    callback: Option<Callback>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Mutex<Option<Duration>>,
+    write_timeout: Option<Duration>,
+    // one long-lived connection, (re-)opened on demand
+    stream: Mutex<Option<TcpStream>>,
+    encoding: Mutex<APDUEncoding>,
 }
 
 impl TransportTCP {
-    pub fn new(host: &str, port: u16, callback: Option<Callback>) -> Self {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self::from_config(&TCPConfig::new(host, port))
+    }
+
+    pub fn from_config(config: &TCPConfig) -> Self {
         Self {
-            url: format!("{}:{}", host, port),
-            callback,
+            url: format!("{}:{}", config.host, config.port),
+            connect_timeout: config.connect_timeout,
+            read_timeout: Mutex::new(config.read_timeout),
+            write_timeout: config.write_timeout,
+            stream: Mutex::new(None),
+            encoding: Mutex::new(APDUEncoding::Short),
+        }
+    }
+
+    /// Sets how long exchanges wait for an answer, `None` waits forever
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().expect("TCP timeout poisoned") = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.read_timeout.lock().expect("TCP timeout poisoned")
+    }
+
+    /// Sets how commands are serialized (see [APDUEncoding])
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        *self.encoding.lock().expect("TCP encoding poisoned") = encoding;
+    }
+
+    pub fn apdu_encoding(&self) -> APDUEncoding {
+        *self.encoding.lock().expect("TCP encoding poisoned")
+    }
+
+    pub(crate) fn connect_error(e: std::io::Error) -> LedgerTCPError {
+        match e.kind() {
+            ErrorKind::ConnectionRefused => LedgerTCPError::ConnectionRefused,
+            _ => LedgerTCPError::ConnectError(e),
+        }
+    }
+
+    // uses the first address that accepts the connection
+    fn connect_with_timeout(&self, timeout: Duration) -> Result<TcpStream, std::io::Error> {
+        let mut last_error = std::io::Error::new(ErrorKind::NotFound, "no address found");
+        for addr in self.url.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn connect(&self, read_timeout: Option<Duration>) -> Result<TcpStream, LedgerTCPError> {
+        let stream = match self.connect_timeout {
+            Some(timeout) => self.connect_with_timeout(timeout),
+            None => TcpStream::connect(&self.url),
         }
+        .map_err(Self::connect_error)?;
+
+        stream
+            .set_read_timeout(read_timeout)
+            .and_then(|_| stream.set_write_timeout(self.write_timeout))
+            .and_then(|_| stream.set_nodelay(true))
+            .map_err(LedgerTCPError::ConnectError)?;
+
+        log::debug!("successfully connected to server {}", &self.url);
+        Ok(stream)
     }
 
-    fn request(raw_command: &[u8], stream: &mut TcpStream) -> Result<Vec<u8>, std::io::Error> {
-        // store length as 32bit big endian into array
-        let send_length_bytes = (raw_command.len() as u32).to_be_bytes();
+    // number of bytes first (32bit big endian), then the bytes
+    pub(crate) fn frame(raw_command: &[u8]) -> Vec<u8> {
+        let mut frame = (raw_command.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(raw_command);
+        frame
+    }
 
-        // first send number of bytes
-        stream.write_all(&send_length_bytes[..])?;
+    // length of the answer following the header (data and return code)
+    pub(crate) fn answer_length(header: [u8; 4]) -> Result<usize, std::io::Error> {
+        // don't allocate whatever a broken peer announces
+        let data_length = u32::from_be_bytes(header);
+        if data_length > MAX_ANSWER_DATA_LENGTH {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("answer too long ({} bytes)", data_length),
+            ));
+        }
 
-        // then send bytes
-        stream.write_all(raw_command)?;
+        // +2 for return code
+        let rcv_length = data_length
+            .checked_add(2)
+            .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidData))?;
+        Ok(rcv_length as usize)
+    }
 
+    fn read_answer(stream: &mut TcpStream) -> Result<Vec<u8>, std::io::Error> {
         let mut rcv_length_bytes = [0u8; 4];
 
         // first read number of bytes
         stream.read_exact(&mut rcv_length_bytes)?;
 
-        // convert bytes to big endian (+2 for return code)
-        let rcv_length = u32::from_be_bytes(rcv_length_bytes) + 2;
-
-        let mut buf = vec![0u8; rcv_length as usize];
+        let mut buf = vec![0u8; Self::answer_length(rcv_length_bytes)?];
         stream.read_exact(&mut buf)?;
         Ok(buf)
     }
 
-    pub fn exchange(
+    // a connection that waits for the next command has nothing to read, anything else
+    // (end of stream, error, stray bytes) means it can't be used anymore
+    fn is_stale(stream: &TcpStream) -> bool {
+        if stream.set_nonblocking(true).is_err() {
+            return true;
+        }
+        let peeked = stream.peek(&mut [0u8; 1]);
+        stream.set_nonblocking(false).is_err()
+            || !matches!(peeked, Err(e) if e.kind() == ErrorKind::WouldBlock)
+    }
+
+    // the peer went away (e.g. simulator restarted) before answering anything
+    pub(crate) fn is_connection_lost(e: &std::io::Error) -> bool {
+        matches!(
+            e.kind(),
+            ErrorKind::BrokenPipe
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::UnexpectedEof
+        )
+    }
+
+    pub(crate) fn map_io_error(e: std::io::Error) -> LedgerTCPError {
+        match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => LedgerTCPError::Timeout,
+            ErrorKind::InvalidData => LedgerTCPError::ResponseError,
+            _ if Self::is_connection_lost(&e) => LedgerTCPError::ConnectionLost,
+            _ => LedgerTCPError::InnerError(e),
+        }
+    }
+
+    pub fn exchange<I: Deref<Target = [u8]>>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Vec<u8>>, LedgerTCPError> {
+        self.exchange_with_timeout(command, self.read_timeout())
+    }
+
+    /// Like [TransportTCP::exchange] with a read timeout for this call only
+    pub fn exchange_with_timeout<I: Deref<Target = [u8]>>(
         &self,
-        command: &APDUCommand<Vec<u8>>,
+        command: &APDUCommand<I>,
+        timeout: Option<Duration>,
     ) -> Result<APDUAnswer<Vec<u8>>, LedgerTCPError> {
-        let raw_command = command.serialize();
+        let raw_command = command
+            .serialize_with(self.apdu_encoding())
+            .map_err(|_| LedgerTCPError::CommandTooLong)?;
 
-        let mut stream = TcpStream::connect(&self.url).map_err(|_| LedgerTCPError::ConnectError)?;
+        let mut guard = self.stream.lock().expect("TCP stream poisoned");
 
-        log::debug!("successfully connected to server {}", &self.url);
+        // a connection that is reused may have been closed by the other side in the meantime
+        let mut reused = false;
+        let mut stream = match guard.take() {
+            Some(stream) if !Self::is_stale(&stream) => {
+                reused = true;
+                stream
+                    .set_read_timeout(timeout)
+                    .map_err(LedgerTCPError::InnerError)?;
+                stream
+            }
+            Some(_) => {
+                log::debug!("connection to {} lost, reconnecting", &self.url);
+                self.connect(timeout)?
+            }
+            None => self.connect(timeout)?,
+        };
+
+        let frame = Self::frame(&raw_command);
+        if let Err(e) = stream.write_all(&frame) {
+            // the device didn't get the command, send it once more on a new connection
+            if !(reused && Self::is_connection_lost(&e)) {
+                return Err(Self::map_io_error(e));
+            }
+            log::debug!("connection to {} lost, reconnecting", &self.url);
+            stream = self.connect(timeout)?;
+            stream.write_all(&frame).map_err(Self::map_io_error)?;
+        }
+
+        let raw_answer = match Self::read_answer(&mut stream) {
+            Ok(raw_answer) => raw_answer,
+            // the command may have been executed already, only repeat it if that's harmless
+            Err(e)
+                if reused
+                    && Self::is_connection_lost(&e)
+                    && RetryPolicy::is_repeatable(command) =>
+            {
+                log::debug!("connection to {} lost, reconnecting", &self.url);
+                stream = self.connect(timeout)?;
+                stream.write_all(&frame).map_err(Self::map_io_error)?;
+                Self::read_answer(&mut stream).map_err(Self::map_io_error)?
+            }
+            // the stream is dropped and will be reopened with the next exchange
+            Err(e) => return Err(Self::map_io_error(e)),
+        };
+        *guard = Some(stream);
+        drop(guard);
 
-        let raw_answer = TransportTCP::request(&raw_command, &mut stream)
-            .map_err(|_| LedgerTCPError::InnerError)?;
         let answer =
             APDUAnswer::from_answer(raw_answer).map_err(|_| LedgerTCPError::ResponseError)?;
 
-        if self.callback.is_some() {
-            (self.callback.unwrap())(command, &answer);
+        Ok(answer)
+    }
+}
+
+impl Exchange for TransportTCP {
+    type Error = LedgerTCPError;
+    type AnswerType = Vec<u8>;
+
+    fn exchange<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.exchange(command)
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.apdu_encoding()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // answers every command with the given raw bytes
+    fn serve_once(raw_answer: Vec<u8>) -> TransportTCP {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut length = [0u8; 4];
+            stream.read_exact(&mut length).unwrap();
+            let mut command = vec![0u8; u32::from_be_bytes(length) as usize];
+            stream.read_exact(&mut command).unwrap();
+            stream.write_all(&raw_answer).unwrap();
+        });
+        TransportTCP::new("127.0.0.1", port)
+    }
+
+    fn command() -> APDUCommand<Vec<u8>> {
+        APDUCommand {
+            cla: 0x7b,
+            ins: 0x10,
+            p1: 0,
+            p2: 0,
+            data: vec![],
         }
+    }
 
-        Ok(answer)
+    #[test]
+    fn answer() {
+        let transport = serve_once(vec![0, 0, 0, 1, 0xaa, 0x90, 0x00]);
+        let answer = transport.exchange(&command()).unwrap();
+        assert_eq!(answer.data(), &[0xaa]);
+        assert_eq!(answer.retcode(), 0x9000);
+    }
+
+    #[test]
+    fn answer_length_overflow() {
+        let transport = serve_once(u32::MAX.to_be_bytes().to_vec());
+        assert!(matches!(
+            transport.exchange(&command()),
+            Err(LedgerTCPError::ResponseError)
+        ));
+    }
+
+    #[test]
+    fn answer_too_long() {
+        let transport = serve_once((MAX_ANSWER_DATA_LENGTH + 1).to_be_bytes().to_vec());
+        assert!(matches!(
+            transport.exchange(&command()),
+            Err(LedgerTCPError::ResponseError)
+        ));
+    }
+
+    // each connection reads its commands and sends the answer, `None` closes the connection
+    // without answering; received commands are counted
+    fn serve(
+        connections: Vec<Vec<Option<Vec<u8>>>>,
+    ) -> (TransportTCP, std::sync::mpsc::Receiver<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            for answers in connections {
+                let (mut stream, _) = listener.accept().unwrap();
+                for answer in answers {
+                    let mut length = [0u8; 4];
+                    stream.read_exact(&mut length).unwrap();
+                    let mut command = vec![0u8; u32::from_be_bytes(length) as usize];
+                    stream.read_exact(&mut command).unwrap();
+                    tx.send(command[1]).unwrap();
+                    match answer {
+                        Some(answer) => stream.write_all(&answer).unwrap(),
+                        None => break,
+                    }
+                }
+            }
+        });
+        (TransportTCP::new("127.0.0.1", port), rx)
+    }
+
+    const OK: [u8; 6] = [0, 0, 0, 0, 0x90, 0x00];
+
+    #[test]
+    fn reconnect_after_close() {
+        // first connection is closed after one answer
+        let (transport, commands) = serve(vec![vec![Some(OK.to_vec())], vec![Some(OK.to_vec())]]);
+        transport.exchange(&command()).unwrap();
+        // give the server some time to close the connection
+        thread::sleep(Duration::from_millis(100));
+        transport.exchange(&command()).unwrap();
+        assert_eq!(commands.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn lost_after_repeatable_command() {
+        let (transport, commands) =
+            serve(vec![vec![Some(OK.to_vec()), None], vec![Some(OK.to_vec())]]);
+        transport.exchange(&command()).unwrap();
+        // get_app_config is sent again on a new connection
+        transport.exchange(&command()).unwrap();
+        assert_eq!(commands.try_iter().collect::<Vec<_>>(), [0x10, 0x10, 0x10]);
+    }
+
+    #[test]
+    fn lost_after_sign() {
+        let (transport, commands) = serve(vec![vec![Some(OK.to_vec()), None]]);
+        transport.exchange(&command()).unwrap();
+        // the command used by `sign` and `sign_essence`
+        assert!(matches!(
+            transport.exchange(&crate::api::sign::command(0)),
+            Err(LedgerTCPError::ConnectionLost)
+        ));
+        // never sent twice
+        assert_eq!(commands.try_iter().collect::<Vec<_>>(), [0x10, 0xa4]);
     }
 }
@@ -1,4 +1,7 @@
 pub(crate) mod ledger_apdu;
 pub(crate) mod ledger_transport;
 pub(crate) mod ledger_transport_hid;
+#[cfg(feature = "speculos-http")]
+pub(crate) mod ledger_transport_http;
+pub(crate) mod ledger_transport_mock;
 pub(crate) mod ledger_transport_tcp;
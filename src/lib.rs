@@ -1,21 +1,49 @@
 //! Library
+//!
+//! [LedgerHardwareWallet] implements the API commands of the IOTA/Shimmer app on top of a
+//! transport ([transport]) and adds the common flows: addresses (also bech32, see [address]),
+//! [LedgerHardwareWallet::sign_essence] and blind signing of the essence hash ([hash]).
+//!
+//! Optional features: `stardust` (essence model), `verify` (checks the returned signatures),
+//! `async` (module `asynchronous`), `speculos-http` (REST API of Speculos) and
+//! `transcript-json`.
 
 use std::convert::TryInto;
+use std::sync::Mutex;
+
+use log::debug;
 
 pub mod ledger;
-pub use ledger::ledger_apdu::{APDUAnswer, APDUCommand};
+pub use ledger::ledger_apdu::{APDUAnswer, APDUCommand, APDUCommandError, APDUEncoding};
 
 use crate::api::constants;
 use crate::api::constants::DataTypeEnum;
 use crate::api::errors::APIError;
+pub use crate::api::sign::{pack_unlocks, Unlock};
 
-pub use crate::ledger::ledger_transport_tcp::Callback;
-pub use crate::transport::{LedgerTransport, Transport, TransportTypes};
+#[cfg(feature = "speculos-http")]
+pub use crate::transport::HTTPConfig;
+pub use crate::transport::{
+    Callback, Exchange, ExchangeCanceller, HIDSelector, LedgerDeviceInfo, LedgerTransport,
+    RetryClass, RetryPolicy, TCPConfig, Transport, TransportMock, TransportTypes,
+};
 
 pub use crate::api::packable::{Error as PackableError, Packable, Read, Write};
 
+pub mod address;
 pub mod api;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod bridge;
+pub mod hash;
+#[cfg(feature = "speculos-http")]
+pub mod speculos;
+#[cfg(feature = "stardust")]
+pub mod stardust;
 pub mod transport;
+pub mod validate;
+#[cfg(feature = "verify")]
+pub mod verify;
 
 const MINIMUM_APP_VERSION: u32 = 6002;
 const MINIMUM_APP_VERSION_GENERATE_PUBLIC_KEYS: u32 = 8007; // generate public keys supported starting with 0.8.7
@@ -48,19 +76,40 @@ impl Packable for LedgerBIP32Index {
     }
 }
 
+/// Remainder (change) output of an essence
+#[derive(Default, Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct LedgerRemainder {
+    /// index of the output in the essence
+    pub output_index: u16,
+    /// key path of the remainder address
+    pub bip32: LedgerBIP32Index,
+}
+
+/// Unlock of one input returned by [LedgerHardwareWallet::sign_essence]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LedgerSignedInput {
+    /// index of the input in the essence
+    pub input_index: u16,
+    /// key path the input was signed with
+    pub bip32: LedgerBIP32Index,
+    /// signature or reference unlock
+    pub unlock: Unlock,
+}
+
 pub enum LedgerDeviceTypes {
     LedgerNanoS,
     LedgerNanoSPlus,
     LedgerNanoX,
 }
 
-pub struct LedgerHardwareWallet {
+pub struct LedgerHardwareWallet<T: Exchange = Transport> {
     version: u32,
-    transport: Transport,
-    transport_type: TransportTypes,
+    transport: T,
     device_type: LedgerDeviceTypes,
     data_buffer_size: usize,
     is_debug_app: bool,
+    // resolved by `set_account`
+    app_mode: Mutex<Option<constants::AppModes>>,
 }
 
 /// Get Ledger by transport_type
@@ -68,7 +117,7 @@ pub fn get_ledger_by_type(
     coin_type: u32,
     bip32_account: u32,
     transport_type: &TransportTypes,
-    callback: Option<crate::ledger::ledger_transport_tcp::Callback>,
+    callback: Option<Callback>,
 ) -> Result<Box<LedgerHardwareWallet>, APIError> {
     let ledger = crate::LedgerHardwareWallet::new(transport_type, callback)?;
 
@@ -78,6 +127,27 @@ pub fn get_ledger_by_type(
     Ok(Box::new(ledger))
 }
 
+/// Get Ledger on top of a caller provided transport
+///
+/// Can be used with any type that implements [Exchange], e.g. a proxy or a test double.
+pub fn get_ledger_by_transport<T: Exchange>(
+    coin_type: u32,
+    bip32_account: u32,
+    transport: T,
+) -> Result<Box<LedgerHardwareWallet<T>>, APIError> {
+    let ledger = crate::LedgerHardwareWallet::from_transport(transport)?;
+
+    // set account
+    ledger.set_account(coin_type, bip32_account)?;
+
+    Ok(Box::new(ledger))
+}
+
+/// List the attached Ledger devices (path, serial, product id, interface)
+pub fn list_ledger_devices() -> Result<Vec<LedgerDeviceInfo>, APIError> {
+    crate::transport::list_devices()
+}
+
 /// Get currently opened app
 /// If "BOLOS" is returned, the dashboard is open
 pub fn get_opened_app(transport_type: &TransportTypes) -> Result<(String, String), APIError> {
@@ -121,6 +191,7 @@ pub fn exit_app(transport_type: &TransportTypes) -> Result<(), APIError> {
 
 /// Get Ledger
 /// If is_simulator is true, you will get a TCP transfer for use with Speculos
+/// (endpoint taken from `LEDGER_SIMULATOR_HOST`/`LEDGER_SIMULATOR_PORT`, see [TCPConfig::from_env])
 /// If it's false, you will get a native USB HID transfer for real devices
 pub fn get_ledger(
     coin_type: u32,
@@ -128,70 +199,260 @@ pub fn get_ledger(
     is_simulator: bool,
 ) -> Result<Box<LedgerHardwareWallet>, APIError> {
     let transport_type = match is_simulator {
-        true => TransportTypes::TCP,
-        false => TransportTypes::NativeHID,
+        true => TransportTypes::TCP(TCPConfig::from_env()),
+        false => TransportTypes::NativeHID(HIDSelector::First),
     };
     get_ledger_by_type(coin_type, bip32_account, &transport_type, None)
 }
 
+// version and device type of the app, fails if the app is too old
+pub(crate) fn app_info(
+    res: &api::get_app_config::Response,
+) -> Result<(u32, LedgerDeviceTypes), APIError> {
+    let version = res.app_version_major as u32 * 1000000
+        + res.app_version_minor as u32 * 1000
+        + res.app_version_patch as u32;
+
+    // signature changed from signing the essence to signing the hash of the essence (0.6.1 to 0.6.2)
+    if version < MINIMUM_APP_VERSION {
+        // temporary for not needing to change wallet.rs
+        return Err(APIError::AppTooOld);
+    }
+
+    let device_type = match res.device {
+        0 => LedgerDeviceTypes::LedgerNanoS,
+        1 => LedgerDeviceTypes::LedgerNanoX,
+        2 => LedgerDeviceTypes::LedgerNanoSPlus,
+        _ => {
+            return Err(APIError::Unknown);
+        }
+    };
+    Ok((version, device_type))
+}
+
+pub(crate) fn buffer_size(dbs: &api::get_data_buffer_state::Response) -> usize {
+    dbs.data_block_size as usize * dbs.data_block_count as usize
+}
+
+// number of blocks to download for the data in the buffer
+pub(crate) fn blocks_to_read(dbs: &api::get_data_buffer_state::Response) -> Result<u8, APIError> {
+    // is buffer state okay? (read allowed, contains addresses, valid flag set)
+    if dbs.data_type as u8 != constants::DataTypeEnum::GeneratedAddress as u8
+        && dbs.data_type as u8 != constants::DataTypeEnum::GeneratedPublicKeys as u8
+        && dbs.data_type as u8 != constants::DataTypeEnum::Signatures as u8
+    {
+        return Err(APIError::CommandNotAllowed);
+    }
+
+    // how many block do we need to read?
+    let mut blocks_needed: u8 = (dbs.data_length / dbs.data_block_size as u16) as u8;
+    if (dbs.data_length % dbs.data_block_size as u16) as u8 != 0 {
+        blocks_needed += 1;
+    }
+
+    // too many blocks?
+    if blocks_needed > dbs.data_block_count {
+        return Err(APIError::CommandInvalidData);
+    }
+    Ok(blocks_needed)
+}
+
+// blocks (number and data) to upload `data` into the empty buffer
+pub(crate) fn blocks_to_write(
+    dbs: &api::get_data_buffer_state::Response,
+    data: &[u8],
+) -> Result<Vec<(u8, Vec<u8>)>, APIError> {
+    // is buffer state okay? (write allowed, is empty)
+    if dbs.data_type as u8 != DataTypeEnum::Empty as u8 {
+        return Err(APIError::CommandNotAllowed);
+    }
+
+    // how many blocks to upload?
+    let mut blocks_needed = (data.len() / dbs.data_block_size as usize) as u8;
+    if (data.len() % dbs.data_block_size as usize) as u8 != 0 {
+        blocks_needed += 1;
+    }
+
+    // too many blocks?
+    if blocks_needed > dbs.data_block_count {
+        return Err(APIError::CommandInvalidData);
+    }
+
+    Ok(data
+        .chunks(dbs.data_block_size as usize)
+        .take(blocks_needed as usize)
+        .enumerate()
+        .map(|(block, chunk)| {
+            // block has to be exactly data_block_size but last chunk can have fewer bytes
+            let mut block_data = chunk.to_vec();
+            block_data.resize(dbs.data_block_size as usize, 0u8);
+            (block as u8, block_data)
+        })
+        .collect())
+}
+
+// sends every command only once, for sequences that are retried as a whole
+struct SingleTry<'a, T>(&'a T);
+
+impl<T: Exchange> Exchange for SingleTry<'_, T> {
+    type Error = T::Error;
+    type AnswerType = T::AnswerType;
+
+    fn exchange<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: std::ops::Deref<Target = [u8]> + Send + Sync,
+    {
+        self.0.exchange_once(command)
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.0.apdu_encoding()
+    }
+}
+
+// each 33 bytes one address, the address type byte is skipped
+pub(crate) fn addresses_from_buffer(
+    buffer: &[u8],
+    count: usize,
+) -> Result<Vec<[u8; constants::ADDRESS_SIZE_BYTES]>, APIError> {
+    if buffer.len() < count * constants::ADDRESS_WITH_TYPE_SIZE_BYTES {
+        return Err(APIError::CommandInvalidData);
+    }
+    Ok(buffer
+        .chunks_exact(constants::ADDRESS_WITH_TYPE_SIZE_BYTES)
+        .take(count)
+        .map(|address| address[1..].try_into().expect("33 byte chunk"))
+        .collect())
+}
+
+// essence followed by the key indices
+pub(crate) fn signing_buffer(
+    essence: Vec<u8>,
+    key_indices: &[LedgerBIP32Index],
+    data_buffer_size: usize,
+) -> Result<Vec<u8>, APIError> {
+    let mut buffer = essence;
+    for key in key_indices.iter() {
+        key.pack(&mut buffer).map_err(|_| APIError::Unknown)?;
+    }
+
+    // we can catch the error here before it happens on the hardware wallet
+    // the wallet would respond with `InvalidData` but an error code indicating
+    // why the data is invalid certainly is helpful.
+    if buffer.len() > data_buffer_size {
+        return Err(APIError::EssenceTooLarge);
+    }
+    Ok(buffer)
+}
+
+// essence hash followed by the number of keys and the key indices
+pub(crate) fn blind_signing_buffer(
+    essence_hash: Vec<u8>,
+    key_indices: &[LedgerBIP32Index],
+    data_buffer_size: usize,
+) -> Result<Vec<u8>, APIError> {
+    if essence_hash.len() != constants::ESSENCE_HASH_SIZE_BYTES {
+        return Err(APIError::CommandInvalidData);
+    }
+
+    let mut buffer = essence_hash;
+    let key_number: u16 = key_indices
+        .len()
+        .try_into()
+        .map_err(|_| APIError::CommandInvalidData)?;
+    key_number
+        .pack(&mut buffer)
+        .map_err(|_| APIError::Unknown)?;
+
+    for key in key_indices.iter() {
+        key.pack(&mut buffer).map_err(|_| APIError::Unknown)?;
+    }
+
+    if buffer.len() > data_buffer_size {
+        return Err(APIError::EssenceTooLarge);
+    }
+    Ok(buffer)
+}
+
+pub(crate) fn unlock_from_response(signature: &api::sign::ResponseVec) -> Result<Unlock, APIError> {
+    Unlock::unpack(&mut &signature.data[..]).map_err(|_| APIError::Unknown)
+}
+
+// signatures are addressed with an u8 index
+pub(crate) fn check_sign_count(count: usize) -> Result<(), APIError> {
+    if count > u8::MAX as usize + 1 {
+        return Err(APIError::CommandInvalidData);
+    }
+    Ok(())
+}
+
+pub(crate) fn check_sign_inputs(inputs: &[LedgerBIP32Index]) -> Result<(), APIError> {
+    if inputs.is_empty() {
+        return Err(APIError::CommandInvalidData);
+    }
+    check_sign_count(inputs.len())
+}
+
 impl LedgerHardwareWallet {
     // creates object but doesn't connect it
     // initialize with dummy-device
     fn new(transport_type: &TransportTypes, callback: Option<Callback>) -> Result<Self, APIError> {
         let transport = crate::transport::create_transport(transport_type, callback)?;
+        Self::from_transport(transport)
+    }
 
-        // reset api
-        crate::api::reset::exec(&transport)?;
+    pub fn get_transport_type(&self) -> TransportTypes {
+        self.transport.transport_type()
+    }
 
-        let res = crate::api::get_app_config::exec(&transport)?;
+    /// Sets how long commands wait for an answer, see [Transport::set_read_timeout]
+    pub fn set_read_timeout(&self, timeout: Option<std::time::Duration>) {
+        self.transport.set_read_timeout(timeout)
+    }
 
-        let version = res.app_version_major as u32 * 1000000
-            + res.app_version_minor as u32 * 1000
-            + res.app_version_patch as u32;
+    /// Sets how failed commands are retried, see [Transport::set_retry_policy]
+    pub fn set_retry_policy(&self, policy: Option<RetryPolicy>) {
+        self.transport.set_retry_policy(policy)
+    }
 
-        // signature changed from signing the essence to signing the hash of the essence (0.6.1 to 0.6.2)
-        if version < MINIMUM_APP_VERSION {
-            // temporary for not needing to change wallet.rs
-            return Err(APIError::AppTooOld);
-        }
+    /// Handle to cancel a running command (e.g. `user_confirm`) from another thread (HID only)
+    pub fn canceller(&self) -> Option<ExchangeCanceller> {
+        self.transport.canceller()
+    }
 
-        let device_type = match res.device {
-            0 => LedgerDeviceTypes::LedgerNanoS,
-            1 => LedgerDeviceTypes::LedgerNanoX,
-            2 => LedgerDeviceTypes::LedgerNanoSPlus,
-            _ => {
-                return Err(APIError::Unknown);
-            }
-        };
+    pub fn is_simulator(&self) -> bool {
+        !matches!(self.transport.transport, LedgerTransport::NativeHID(_))
+    }
+}
+
+impl<T: Exchange> LedgerHardwareWallet<T> {
+    /// Creates the object on top of an already opened transport
+    pub fn from_transport(transport: T) -> Result<Self, APIError> {
+        // reset api
+        crate::api::reset::exec(&transport)?;
+
+        let res = crate::api::get_app_config::exec(&transport)?;
+        let (version, device_type) = app_info(&res)?;
 
         let data_buffer_state = crate::api::get_data_buffer_state::exec(&transport)?;
 
         Ok(LedgerHardwareWallet {
             version,
             transport,
-            transport_type: *transport_type,
             device_type,
-            data_buffer_size: data_buffer_state.data_block_size as usize
-                * data_buffer_state.data_block_count as usize,
+            data_buffer_size: buffer_size(&data_buffer_state),
             is_debug_app: res.is_debug_app == 1,
+            app_mode: Mutex::new(None),
         })
     }
 
-    fn transport(&self) -> &Transport {
+    fn transport(&self) -> &T {
         &self.transport
     }
 
-    pub fn get_transport_type(&self) -> TransportTypes {
-        self.transport_type
-    }
-
-    pub fn is_simulator(&self) -> bool {
-        match self.transport.transport {
-            LedgerTransport::TCP(_) => true,
-            LedgerTransport::NativeHID(_) => false,
-        }
-    }
-
     // something like tri-state ... true / false / error
     pub fn is_debug_app(&self) -> bool {
         self.is_debug_app
@@ -208,7 +469,7 @@ impl LedgerHardwareWallet {
     // uses the get_data_buffer_state-Api call to figure out if the ledger is locked
     pub fn is_locked(&self) -> Result<bool, APIError> {
         match api::get_data_buffer_state::exec(self.transport()) {
-            Err(APIError::SecurityStatusNotSatisfied) => Ok(true),
+            Err(e) if e.is_device_locked() => Ok(true),
             Ok(_) => Ok(false),
             Err(e) => Err(e),
         }
@@ -220,29 +481,11 @@ impl LedgerHardwareWallet {
     fn read_data_bufer(&self) -> Result<Vec<u8>, APIError> {
         // get buffer state
         let dbs = api::get_data_buffer_state::exec(self.transport())?;
-
-        // is buffer state okay? (read allowed, contains addresses, valid flag set)
-        if dbs.data_type as u8 != constants::DataTypeEnum::GeneratedAddress as u8
-            && dbs.data_type as u8 != constants::DataTypeEnum::GeneratedPublicKeys as u8
-            && dbs.data_type as u8 != constants::DataTypeEnum::Signatures as u8
-        {
-            return Err(APIError::CommandNotAllowed);
-        }
+        let blocks_needed = blocks_to_read(&dbs)?;
 
         // buffer to read data from device
         let mut buffer: Vec<u8> = Vec::new();
 
-        // how many block do we need to read?
-        let mut blocks_needed: u8 = (dbs.data_length / dbs.data_block_size as u16) as u8;
-        if (dbs.data_length % dbs.data_block_size as u16) as u8 != 0 {
-            blocks_needed += 1;
-        }
-
-        // too many blocks?
-        if blocks_needed > dbs.data_block_count {
-            return Err(APIError::CommandInvalidData);
-        }
-
         // read blocks to buffer
         for block in 0..blocks_needed {
             // read data buffer to get address
@@ -253,44 +496,39 @@ impl LedgerHardwareWallet {
     }
 
     // convenience function - write as many pages as needed to transfer data to the device
+    //
+    // single blocks aren't repeated, with a retry policy the whole upload is restarted
     fn write_data_buffer(&self, data: Vec<u8>) -> Result<(), APIError> {
-        // clear data buffer before data can be uploaded and validated
-        api::clear_data_buffer::exec(self.transport())?;
-
-        // get buffer state
-        let dbs = api::get_data_buffer_state::exec(self.transport())?;
-
-        // is buffer state okay? (write allowed, is empty)
-        if dbs.data_type as u8 != DataTypeEnum::Empty as u8 {
-            return Err(APIError::CommandNotAllowed);
-        }
+        let policy = match self.transport().retry_policy() {
+            Some(policy) => policy,
+            None => return self.try_write_data_buffer(&data),
+        };
 
-        // how many blocks to upload?
-        let mut blocks_needed = (data.len() / dbs.data_block_size as usize) as u8;
-        if (data.len() % dbs.data_block_size as usize) as u8 != 0 {
-            blocks_needed += 1;
+        let mut attempt = 1;
+        loop {
+            match self.try_write_data_buffer(&data) {
+                Err(e) if policy.should_retry(&e, attempt) => {
+                    std::thread::sleep(policy.backoff(attempt));
+                    attempt += 1;
+                }
+                res => return res,
+            }
         }
+    }
 
-        // too many blocks?
-        if blocks_needed > dbs.data_block_count {
-            return Err(APIError::CommandInvalidData);
-        }
+    fn try_write_data_buffer(&self, data: &[u8]) -> Result<(), APIError> {
+        // the upload is retried as a whole, not every single command
+        let transport = SingleTry(self.transport());
 
-        // transfer blocks
-        let mut iter = data.chunks(dbs.data_block_size as usize);
-        for block in 0..blocks_needed {
-            // get next chunk of data
-            let mut block_data = iter.next().unwrap().to_vec();
+        // clear data buffer before data can be uploaded and validated
+        api::clear_data_buffer::exec(&transport)?;
 
-            // block has to be exactly data_block_size but last chunk can have fewer bytes
-            // fill it up to the correct size
-            // TODO: is there some nicer way?
-            while block_data.len() < dbs.data_block_size as usize {
-                block_data.push(0u8);
-            }
+        // get buffer state
+        let dbs = api::get_data_buffer_state::exec(&transport)?;
 
-            // now write block
-            api::write_data_block::exec(self.transport(), block, block_data)?;
+        // transfer blocks
+        for (block, block_data) in blocks_to_write(&dbs, data)? {
+            api::write_data_block::exec(&transport, block, block_data)?;
         }
         Ok(())
     }
@@ -310,10 +548,27 @@ impl LedgerHardwareWallet {
     pub fn set_account(&self, coin_type: u32, bip32_account: u32) -> Result<(), APIError> {
         let app_config = crate::api::get_app_config::exec(self.transport())?;
 
-        api::set_account::exec(coin_type, app_config, self.transport(), bip32_account)?;
+        let app_mode =
+            api::set_account::exec(coin_type, app_config, self.transport(), bip32_account)?;
+        *self.app_mode.lock().expect("app mode poisoned") = Some(app_mode);
         Ok(())
     }
 
+    /// App mode selected by the last `set_account`
+    pub fn app_mode(&self) -> Option<constants::AppModes> {
+        *self.app_mode.lock().expect("app mode poisoned")
+    }
+
+    /// Bech32 HRP of the addresses of the app mode (`iota`, `atoi`, `smr` or `rms`)
+    pub fn hrp(&self) -> Option<&'static str> {
+        self.app_mode().map(|mode| mode.hrp())
+    }
+
+    // checked before addresses are generated
+    fn required_hrp(&self) -> Result<&'static str, APIError> {
+        self.hrp().ok_or(APIError::AccountNotSet)
+    }
+
     pub fn get_addresses(
         &self,
         show: bool,
@@ -334,18 +589,22 @@ impl LedgerHardwareWallet {
 
         // read addresses from device
         let buffer = self.read_data_bufer()?;
+        addresses_from_buffer(&buffer, count)
+    }
 
-        let mut addresses: Vec<[u8; 32]> = Vec::new();
-        for i in 0_usize..count {
-            // no need to copy address type byte!
-            let addr: [u8; 32] = buffer[i * constants::ADDRESS_WITH_TYPE_SIZE_BYTES + 1
-                ..(i + 1) * constants::ADDRESS_WITH_TYPE_SIZE_BYTES]
-                .try_into()
-                .unwrap(); // each 33 bytes one address
-            addresses.push(addr);
-        }
-
-        Ok(addresses)
+    /// Like [LedgerHardwareWallet::get_addresses] but returns bech32 addresses with the HRP
+    /// of the app mode
+    pub fn get_bech32_addresses(
+        &self,
+        show: bool,
+        bip32: LedgerBIP32Index,
+        count: usize,
+    ) -> Result<Vec<String>, api::errors::APIError> {
+        let hrp = self.required_hrp()?;
+        self.get_addresses(show, bip32, count)?
+            .iter()
+            .map(|address| Ok(address::to_bech32(hrp, address)?))
+            .collect()
     }
 
     pub fn get_public_keys(
@@ -405,12 +664,14 @@ impl LedgerHardwareWallet {
 
         // read addresses from device
         let buffer = self.read_data_bufer()?;
+        Ok(addresses_from_buffer(&buffer, 1)?[0])
+    }
 
-        // no need to copy address type byte!
-        let addr = buffer[1..constants::ADDRESS_WITH_TYPE_SIZE_BYTES]
-            .try_into()
-            .unwrap();
-        Ok(addr)
+    /// Like [LedgerHardwareWallet::get_first_address] but returns the bech32 address with the
+    /// HRP of the app mode
+    pub fn get_first_bech32_address(&self) -> Result<String, api::errors::APIError> {
+        let hrp = self.required_hrp()?;
+        Ok(address::to_bech32(hrp, &self.get_first_address()?)?)
     }
 
     /// Prepare Signing
@@ -424,20 +685,9 @@ impl LedgerHardwareWallet {
         remainder_index: u16,
         remainder: LedgerBIP32Index,
     ) -> Result<(), api::errors::APIError> {
-        // clone buffer because we have to add the key indices after the essence
-        let mut buffer: Vec<u8> = essence.to_vec();
-        for key in key_indices.iter() {
-            key.pack(&mut buffer).map_err(|_| APIError::Unknown)?;
-        }
+        let buffer = signing_buffer(essence, &key_indices, self.data_buffer_size)?;
         let buffer_len = buffer.len();
 
-        // we can catch the error here before it happens on the hardware wallet
-        // the wallet would respond with `InvalidData` but an error code indicating
-        // why the data is invalid certainly is helpful.
-        if buffer_len > self.data_buffer_size {
-            return Err(api::errors::APIError::EssenceTooLarge);
-        }
-
         // write data to the device
         self.write_data_buffer(buffer)?;
 
@@ -456,6 +706,18 @@ impl LedgerHardwareWallet {
         Ok(())
     }
 
+    /// Checks the essence and key paths before `prepare_signing` or `sign_essence`
+    ///
+    /// See [validate::validate_essence], the buffer size of this device is used.
+    pub fn validate_essence(
+        &self,
+        essence: &[u8],
+        key_indices: &[LedgerBIP32Index],
+        remainder: Option<&LedgerRemainder>,
+    ) -> Result<(), validate::LedgerEssenceError> {
+        validate::validate_essence(essence, key_indices, remainder, self.data_buffer_size)
+    }
+
     /// Prepare Blind Signing
     ///
     /// Uploads the essence hash and validates it
@@ -464,16 +726,7 @@ impl LedgerHardwareWallet {
         key_indices: Vec<LedgerBIP32Index>,
         essence_hash: Vec<u8>,
     ) -> Result<(), api::errors::APIError> {
-        // clone buffer because we have to add the key indices after the essence
-        let mut buffer: Vec<u8> = essence_hash.to_vec();
-        let key_number: u16 = key_indices.len() as u16;
-        key_number
-            .pack(&mut buffer)
-            .map_err(|_| APIError::Unknown)?;
-
-        for key in key_indices.iter() {
-            key.pack(&mut buffer).map_err(|_| APIError::Unknown)?;
-        }
+        let buffer = blind_signing_buffer(essence_hash, &key_indices, self.data_buffer_size)?;
         let buffer_len = buffer.len();
 
         // write data to the device
@@ -494,6 +747,20 @@ impl LedgerHardwareWallet {
         Ok(())
     }
 
+    /// Prepare Blind Signing from the serialized essence
+    ///
+    /// Hashes the essence, uploads the hash and validates it. Returns the hash that is shown
+    /// on the device.
+    pub fn prepare_blind_signing_essence(
+        &self,
+        key_indices: Vec<LedgerBIP32Index>,
+        essence: &[u8],
+    ) -> Result<[u8; constants::ESSENCE_HASH_SIZE_BYTES], api::errors::APIError> {
+        let essence_hash = hash::essence_hash(essence);
+        self.prepare_blind_signing(key_indices, essence_hash.to_vec())?;
+        Ok(essence_hash)
+    }
+
     /// User Confirm
     ///
     /// Displays the (parsed and validated) essence in human readable form on the screen of the
@@ -506,15 +773,75 @@ impl LedgerHardwareWallet {
     /// Sign
     ///
     /// The publicly usable function for signing an essence.
-    pub fn sign(&self, num_inputs: u16) -> Result<Vec<u8>, api::errors::APIError> {
-        let mut signatures: Vec<u8> = Vec::new();
+    ///
+    /// Returns one unlock per input, [Unlock::to_bytes] or [pack_unlocks] give the
+    /// Stardust wire format. At most 256 inputs can be signed.
+    pub fn sign(&self, num_inputs: u16) -> Result<Vec<Unlock>, api::errors::APIError> {
+        check_sign_count(num_inputs as usize)?;
+        (0..num_inputs)
+            .map(|signature_idx| self.sign_single(signature_idx as u8))
+            .collect()
+    }
 
-        for signature_idx in 0..num_inputs as u8 {
-            let mut signature = api::sign::exec(self.transport(), signature_idx)?;
-            signatures.append(&mut signature.data);
+    fn sign_single(&self, signature_idx: u8) -> Result<Unlock, APIError> {
+        let signature = api::sign::exec(self.transport(), signature_idx)?;
+        unlock_from_response(&signature)
+    }
+
+    /// Sign Essence
+    ///
+    /// Runs `prepare_signing`, `user_confirm` and `sign` for an essence with one key path per
+    /// input and returns the unlocks in input order. The essence is checked with
+    /// [LedgerHardwareWallet::validate_essence] before it's uploaded, problems are reported as
+    /// `APIError::Essence`. The data buffer is cleared if any step fails (including rejection
+    /// by the user).
+    pub fn sign_essence(
+        &self,
+        essence: Vec<u8>,
+        inputs: Vec<LedgerBIP32Index>,
+        remainder: Option<LedgerRemainder>,
+    ) -> Result<Vec<LedgerSignedInput>, APIError> {
+        check_sign_inputs(&inputs)?;
+        self.validate_essence(&essence, &inputs, remainder.as_ref())?;
+
+        let res = self.try_sign_essence(essence, inputs, remainder);
+        if res.is_err() {
+            // don't leave the essence or partial signatures on the device
+            if let Err(e) = api::clear_data_buffer::exec(self.transport()) {
+                debug!("clearing data buffer failed: {}", e);
+            }
         }
+        res
+    }
+
+    fn try_sign_essence(
+        &self,
+        essence: Vec<u8>,
+        inputs: Vec<LedgerBIP32Index>,
+        remainder: Option<LedgerRemainder>,
+    ) -> Result<Vec<LedgerSignedInput>, APIError> {
+        let remainder_output = remainder.unwrap_or_default();
+        self.prepare_signing(
+            inputs.clone(),
+            essence,
+            remainder.is_some(),
+            remainder_output.output_index,
+            remainder_output.bip32,
+        )?;
 
-        Ok(signatures)
+        self.user_confirm()?;
+
+        inputs
+            .into_iter()
+            .enumerate()
+            .map(|(input_index, bip32)| {
+                Ok(LedgerSignedInput {
+                    input_index: input_index as u16,
+                    bip32,
+                    unlock: self.sign_single(input_index as u8)?,
+                })
+            })
+            .collect()
     }
 
     // methods only available if compiled with APP_DEBUG flag
@@ -0,0 +1,342 @@
+//! Client for the automation part of the Speculos REST API
+//!
+//! Presses buttons and reads the texts shown on the screen of the simulator so that
+//! flows that need user interaction (e.g. `user_confirm`, `get_addresses` with `show=true`)
+//! can run unattended against release builds of the app.
+//!
+//! Commands that wait for the user block the transport, so the client is meant to be
+//! driven from another thread while the command is running.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::transport::errors::LedgerHTTPError;
+use crate::transport::HTTPConfig;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Right,
+    Both,
+}
+
+impl Button {
+    fn path(&self) -> &'static str {
+        match self {
+            Button::Left => "left",
+            Button::Right => "right",
+            Button::Both => "both",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ButtonAction {
+    Press,
+    Release,
+    PressAndRelease,
+}
+
+impl ButtonAction {
+    fn name(&self) -> &'static str {
+        match self {
+            ButtonAction::Press => "press",
+            ButtonAction::Release => "release",
+            ButtonAction::PressAndRelease => "press-and-release",
+        }
+    }
+}
+
+/// A text event as reported by Speculos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenEvent {
+    pub text: String,
+    pub x: i64,
+    pub y: i64,
+}
+
+pub struct SpeculosClient {
+    base_url: String,
+    agent: ureq::Agent,
+}
+
+impl SpeculosClient {
+    pub fn new(config: &HTTPConfig) -> Self {
+        Self {
+            base_url: config.base_url(),
+            agent: config.agent(),
+        }
+    }
+
+    /// Presses and releases a button
+    pub fn press(&self, button: Button) -> Result<(), LedgerHTTPError> {
+        self.button(button, ButtonAction::PressAndRelease)
+    }
+
+    /// Sends a single button action, e.g. to hold a button with [ButtonAction::Press]
+    pub fn button(&self, button: Button, action: ButtonAction) -> Result<(), LedgerHTTPError> {
+        self.agent
+            .post(&format!("{}/button/{}", self.base_url, button.path()))
+            .send_json(serde_json::json!({ "action": action.name() }))?;
+        Ok(())
+    }
+
+    /// All text events since the start (or the last [SpeculosClient::clear_events])
+    pub fn events(&self) -> Result<Vec<ScreenEvent>, LedgerHTTPError> {
+        self.get_events(false)
+    }
+
+    /// Text events of the screen that is currently shown
+    pub fn current_screen(&self) -> Result<Vec<ScreenEvent>, LedgerHTTPError> {
+        self.get_events(true)
+    }
+
+    pub fn clear_events(&self) -> Result<(), LedgerHTTPError> {
+        self.agent
+            .delete(&format!("{}/events", self.base_url))
+            .call()?;
+        Ok(())
+    }
+
+    /// Polls the current screen until `text` is shown
+    pub fn wait_for_text(
+        &self,
+        text: &str,
+        timeout: Duration,
+    ) -> Result<ScreenEvent, LedgerHTTPError> {
+        let start_time = Instant::now();
+        loop {
+            if let Some(event) = self.find_text(text)? {
+                return Ok(event);
+            }
+            if start_time.elapsed() >= timeout {
+                return Err(LedgerHTTPError::Timeout);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Presses `button` until `text` is shown, at most `max_presses` times
+    ///
+    /// Typically used with [Button::Right] to skip through the review screens
+    /// until e.g. "Accept" is shown which then is confirmed with [Button::Both].
+    pub fn navigate_to(
+        &self,
+        text: &str,
+        button: Button,
+        max_presses: usize,
+    ) -> Result<ScreenEvent, LedgerHTTPError> {
+        for _ in 0..max_presses {
+            if let Some(event) = self.find_text(text)? {
+                return Ok(event);
+            }
+            self.press(button)?;
+            // give the app some time to draw the next screen
+            thread::sleep(POLL_INTERVAL);
+        }
+        self.find_text(text)?.ok_or(LedgerHTTPError::Timeout)
+    }
+
+    fn find_text(&self, text: &str) -> Result<Option<ScreenEvent>, LedgerHTTPError> {
+        Ok(self
+            .current_screen()?
+            .into_iter()
+            .find(|event| event.text.contains(text)))
+    }
+
+    fn get_events(&self, current_screen_only: bool) -> Result<Vec<ScreenEvent>, LedgerHTTPError> {
+        let body: serde_json::Value = self
+            .agent
+            .get(&format!("{}/events", self.base_url))
+            .query(
+                "currentscreenonly",
+                if current_screen_only { "true" } else { "false" },
+            )
+            .call()?
+            .into_json()
+            .map_err(|_| LedgerHTTPError::ResponseError)?;
+
+        let events = body["events"]
+            .as_array()
+            .ok_or(LedgerHTTPError::ResponseError)?;
+
+        events
+            .iter()
+            .map(|event| {
+                Ok(ScreenEvent {
+                    text: event["text"]
+                        .as_str()
+                        .ok_or(LedgerHTTPError::ResponseError)?
+                        .to_string(),
+                    x: event["x"].as_i64().unwrap_or_default(),
+                    y: event["y"].as_i64().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::Receiver;
+
+    use crate::ledger::ledger_transport_http::stand_in::{self, Request, Response};
+
+    fn client(responses: Vec<Response>) -> (SpeculosClient, Receiver<Request>) {
+        let (port, requests) = stand_in::serve(responses);
+        (
+            SpeculosClient::new(&HTTPConfig::new("127.0.0.1", port)),
+            requests,
+        )
+    }
+
+    fn json(body: &str) -> serde_json::Value {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn buttons() {
+        let (client, requests) = client(vec![
+            Response::ok("{}"),
+            Response::ok("{}"),
+            Response::ok("{}"),
+        ]);
+        client.button(Button::Left, ButtonAction::Press).unwrap();
+        client.button(Button::Left, ButtonAction::Release).unwrap();
+        client.press(Button::Both).unwrap();
+
+        let expected = [
+            ("/button/left", "press"),
+            ("/button/left", "release"),
+            ("/button/both", "press-and-release"),
+        ];
+        for (path, action) in expected {
+            let request = requests.recv().unwrap();
+            assert_eq!(request.method, "POST");
+            assert_eq!(request.path, path);
+            assert_eq!(json(&request.body), serde_json::json!({ "action": action }));
+        }
+    }
+
+    #[test]
+    fn events() {
+        let (client, requests) = client(vec![
+            Response::ok(
+                r#"{"events": [{"text": "Review", "x": 41, "y": 3}, {"text": "Transaction", "x": 30, "y": 17}]}"#,
+            ),
+            Response::ok(r#"{"events": [{"text": "Accept", "x": 43, "y": 10}]}"#),
+        ]);
+
+        assert_eq!(
+            client.events().unwrap(),
+            vec![
+                ScreenEvent {
+                    text: String::from("Review"),
+                    x: 41,
+                    y: 3
+                },
+                ScreenEvent {
+                    text: String::from("Transaction"),
+                    x: 30,
+                    y: 17
+                },
+            ]
+        );
+        let request = requests.recv().unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/events?currentscreenonly=false");
+
+        let event = client
+            .wait_for_text("Accept", Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(event.text, "Accept");
+        assert_eq!(
+            requests.recv().unwrap().path,
+            "/events?currentscreenonly=true"
+        );
+    }
+
+    fn screen(text: &str) -> Response {
+        Response::ok(&format!(
+            r#"{{"events": [{{"text": "{}", "x": 0, "y": 0}}]}}"#,
+            text
+        ))
+    }
+
+    const CURRENT_SCREEN: &str = "/events?currentscreenonly=true";
+
+    #[test]
+    fn wait_for_text_timeout() {
+        let (client, requests) = client((0..10).map(|_| screen("Review")).collect());
+
+        assert!(matches!(
+            client.wait_for_text("Accept", Duration::from_millis(250)),
+            Err(LedgerHTTPError::Timeout)
+        ));
+        // polled until the timeout, one request per poll
+        let polls: Vec<_> = requests.try_iter().collect();
+        assert!((2..10).contains(&polls.len()), "{} polls", polls.len());
+        for request in polls {
+            assert_eq!(
+                (request.method.as_str(), request.path.as_str()),
+                ("GET", CURRENT_SCREEN)
+            );
+        }
+    }
+
+    #[test]
+    fn navigate_to() {
+        let (client, requests) = client(vec![
+            screen("Review"),
+            Response::ok("{}"),
+            screen("Amount"),
+            Response::ok("{}"),
+            screen("Accept"),
+        ]);
+
+        let event = client.navigate_to("Accept", Button::Right, 5).unwrap();
+        assert_eq!(event.text, "Accept");
+
+        let requests: Vec<_> = requests
+            .try_iter()
+            .map(|request| {
+                if request.method == "POST" {
+                    assert_eq!(
+                        json(&request.body),
+                        serde_json::json!({ "action": "press-and-release" })
+                    );
+                }
+                (request.method, request.path)
+            })
+            .collect();
+        let get = || (String::from("GET"), String::from(CURRENT_SCREEN));
+        let right = || (String::from("POST"), String::from("/button/right"));
+        assert_eq!(requests, [get(), right(), get(), right(), get()]);
+    }
+
+    #[test]
+    fn navigate_to_not_found() {
+        let (client, requests) =
+            client(vec![screen("Review"), Response::ok("{}"), screen("Amount")]);
+
+        assert!(matches!(
+            client.navigate_to("Accept", Button::Right, 1),
+            Err(LedgerHTTPError::Timeout)
+        ));
+        let paths: Vec<_> = requests.try_iter().map(|request| request.path).collect();
+        assert_eq!(paths, [CURRENT_SCREEN, "/button/right", CURRENT_SCREEN]);
+    }
+
+    #[test]
+    fn invalid_events() {
+        let (client, _requests) = client(vec![Response::ok(r#"{"events": [{"x": 1}]}"#)]);
+        assert!(matches!(
+            client.events(),
+            Err(LedgerHTTPError::ResponseError)
+        ));
+    }
+}
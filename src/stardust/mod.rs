@@ -0,0 +1,354 @@
+//! Minimal Stardust transaction essence (feature `stardust`)
+//!
+//! Models UTXO inputs, Basic outputs (with native tokens, unlock conditions and features) and an
+//! optional tagged data payload. The serialization follows TIP-20/TIP-41/TIP-45, so `to_bytes()`
+//! can be passed to `prepare_signing` without `bee-block`.
+//!
+//! The app signs only a subset: Basic outputs without native tokens and features, with a
+//! single address unlock condition of an Ed25519 address (see [crate::validate]).
+
+mod output;
+
+use std::convert::TryInto;
+
+pub use output::*;
+
+use crate::api::constants::ESSENCE_HASH_SIZE_BYTES;
+use crate::api::packable::{Error as PackableError, Packable, Read, Write};
+use crate::hash;
+
+pub const REGULAR_TRANSACTION_ESSENCE_TYPE: u8 = 1;
+pub const UTXO_INPUT_TYPE: u8 = 0;
+pub const TAGGED_DATA_PAYLOAD_TYPE: u32 = 5;
+
+pub const TRANSACTION_ID_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct UtxoInput {
+    pub transaction_id: [u8; TRANSACTION_ID_LENGTH],
+    pub output_index: u16,
+}
+
+impl Packable for UtxoInput {
+    fn packed_len(&self) -> usize {
+        1 + TRANSACTION_ID_LENGTH + 2
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        UTXO_INPUT_TYPE.pack(buf)?;
+        buf.write_all(&self.transaction_id)?;
+        self.output_index.pack(buf)
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        if u8::unpack(buf)? != UTXO_INPUT_TYPE {
+            return Err(PackableError::InvalidType);
+        }
+        Ok(Self {
+            transaction_id: read_array(buf)?,
+            output_index: u16::unpack(buf)?,
+        })
+    }
+}
+
+/// Tagged data payload (tag up to 64 bytes)
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct TaggedDataPayload {
+    pub tag: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl Packable for TaggedDataPayload {
+    fn packed_len(&self) -> usize {
+        4 + 1 + self.tag.len() + 4 + self.data.len()
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        TAGGED_DATA_PAYLOAD_TYPE.pack(buf)?;
+        pack_bytes_u8(&self.tag, buf)?;
+        let data_len: u32 = self
+            .data
+            .len()
+            .try_into()
+            .map_err(|_| PackableError::InvalidAnnouncedLen)?;
+        data_len.pack(buf)?;
+        buf.write_all(&self.data)?;
+        Ok(())
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        if u32::unpack(buf)? != TAGGED_DATA_PAYLOAD_TYPE {
+            return Err(PackableError::InvalidType);
+        }
+        let tag = unpack_bytes_u8(buf)?;
+        // don't trust the length for the allocation
+        let len = u32::unpack(buf)? as usize;
+        let mut data = Vec::new();
+        buf.take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(PackableError::InvalidAnnouncedLen);
+        }
+        Ok(Self { tag, data })
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct RegularTransactionEssence {
+    /// see [network_id]
+    pub network_id: u64,
+    pub inputs: Vec<UtxoInput>,
+    /// see [inputs_commitment]
+    pub inputs_commitment: [u8; 32],
+    pub outputs: Vec<BasicOutput>,
+    pub payload: Option<TaggedDataPayload>,
+}
+
+impl RegularTransactionEssence {
+    /// Serialized essence as expected by `prepare_signing`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PackableError> {
+        let mut buf = Vec::with_capacity(self.packed_len());
+        self.pack(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Blake2b-256 hash of the serialized essence (the value that is signed)
+    pub fn hash(&self) -> Result<[u8; ESSENCE_HASH_SIZE_BYTES], PackableError> {
+        Ok(hash::essence_hash(&self.to_bytes()?))
+    }
+}
+
+impl Packable for RegularTransactionEssence {
+    fn packed_len(&self) -> usize {
+        1 + 8
+            + 2
+            + self.inputs.iter().map(Packable::packed_len).sum::<usize>()
+            + 32
+            + 2
+            + self.outputs.iter().map(Packable::packed_len).sum::<usize>()
+            + 4
+            + self.payload.as_ref().map_or(0, Packable::packed_len)
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        REGULAR_TRANSACTION_ESSENCE_TYPE.pack(buf)?;
+        self.network_id.pack(buf)?;
+        pack_vec_u16(&self.inputs, buf)?;
+        buf.write_all(&self.inputs_commitment)?;
+        pack_vec_u16(&self.outputs, buf)?;
+        match &self.payload {
+            Some(payload) => {
+                (payload.packed_len() as u32).pack(buf)?;
+                payload.pack(buf)
+            }
+            None => 0u32.pack(buf),
+        }
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        if u8::unpack(buf)? != REGULAR_TRANSACTION_ESSENCE_TYPE {
+            return Err(PackableError::InvalidType);
+        }
+        let network_id = u64::unpack(buf)?;
+        let inputs = unpack_vec_u16(buf)?;
+        let inputs_commitment = read_array(buf)?;
+        let outputs = unpack_vec_u16(buf)?;
+        let payload = match u32::unpack(buf)? {
+            0 => None,
+            len => {
+                let payload = TaggedDataPayload::unpack(buf)?;
+                if payload.packed_len() != len as usize {
+                    return Err(PackableError::InvalidAnnouncedLen);
+                }
+                Some(payload)
+            }
+        };
+        Ok(Self {
+            network_id,
+            inputs,
+            inputs_commitment,
+            outputs,
+            payload,
+        })
+    }
+}
+
+/// Network ID of a network name (e.g. `shimmer`, `testnet`)
+pub fn network_id(network_name: &str) -> u64 {
+    let hash = hash::blake2b_256(network_name.as_bytes());
+    u64::from_le_bytes(hash[..8].try_into().expect("8 bytes"))
+}
+
+/// Commitment to the consumed outputs (in the order of the inputs)
+pub fn inputs_commitment(consumed_outputs: &[BasicOutput]) -> Result<[u8; 32], PackableError> {
+    let mut hashes = Vec::with_capacity(consumed_outputs.len() * 32);
+    for output in consumed_outputs {
+        let mut bytes = Vec::with_capacity(output.packed_len());
+        output.pack(&mut bytes)?;
+        hashes.extend_from_slice(&hash::blake2b_256(&bytes));
+    }
+    Ok(hash::blake2b_256(&hashes))
+}
+
+fn read_array<R: Read, const N: usize>(buf: &mut R) -> Result<[u8; N], PackableError> {
+    let mut bytes = [0u8; N];
+    buf.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn pack_bytes_u8<W: Write>(bytes: &[u8], buf: &mut W) -> Result<(), PackableError> {
+    let len: u8 = bytes
+        .len()
+        .try_into()
+        .map_err(|_| PackableError::InvalidAnnouncedLen)?;
+    len.pack(buf)?;
+    buf.write_all(bytes)?;
+    Ok(())
+}
+
+fn pack_bytes_u16<W: Write>(bytes: &[u8], buf: &mut W) -> Result<(), PackableError> {
+    let len: u16 = bytes
+        .len()
+        .try_into()
+        .map_err(|_| PackableError::InvalidAnnouncedLen)?;
+    len.pack(buf)?;
+    buf.write_all(bytes)?;
+    Ok(())
+}
+
+fn unpack_bytes_u8<R: Read>(buf: &mut R) -> Result<Vec<u8>, PackableError> {
+    let mut bytes = vec![0u8; u8::unpack(buf)? as usize];
+    buf.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn unpack_bytes_u16<R: Read>(buf: &mut R) -> Result<Vec<u8>, PackableError> {
+    let mut bytes = vec![0u8; u16::unpack(buf)? as usize];
+    buf.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn pack_vec_u8<P: Packable, W: Write>(items: &[P], buf: &mut W) -> Result<(), PackableError> {
+    let count: u8 = items
+        .len()
+        .try_into()
+        .map_err(|_| PackableError::InvalidAnnouncedLen)?;
+    count.pack(buf)?;
+    items.iter().try_for_each(|item| item.pack(buf))
+}
+
+fn pack_vec_u16<P: Packable, W: Write>(items: &[P], buf: &mut W) -> Result<(), PackableError> {
+    let count: u16 = items
+        .len()
+        .try_into()
+        .map_err(|_| PackableError::InvalidAnnouncedLen)?;
+    count.pack(buf)?;
+    items.iter().try_for_each(|item| item.pack(buf))
+}
+
+fn unpack_vec_u8<P: Packable, R: Read>(buf: &mut R) -> Result<Vec<P>, PackableError> {
+    (0..u8::unpack(buf)?).map(|_| P::unpack(buf)).collect()
+}
+
+fn unpack_vec_u16<P: Packable, R: Read>(buf: &mut R) -> Result<Vec<P>, PackableError> {
+    (0..u16::unpack(buf)?).map(|_| P::unpack(buf)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn essence() -> RegularTransactionEssence {
+        RegularTransactionEssence {
+            network_id: network_id("shimmer"),
+            inputs: vec![
+                UtxoInput {
+                    transaction_id: [1; TRANSACTION_ID_LENGTH],
+                    output_index: 0,
+                },
+                UtxoInput {
+                    transaction_id: [2; TRANSACTION_ID_LENGTH],
+                    output_index: 5,
+                },
+            ],
+            inputs_commitment: [3; 32],
+            outputs: vec![
+                BasicOutput::new(1_000_000, Address::Ed25519([4; 32])),
+                BasicOutput::new(42, Address::Ed25519([5; 32])),
+            ],
+            payload: None,
+        }
+    }
+
+    #[test]
+    fn essence_hash() {
+        // blake2b-256 of the TIP-45 bytes, computed independently
+        assert_eq!(
+            hex::encode(essence().hash().unwrap()),
+            "7d7bd935f0283a30d0d37d5cdea1f65cb4807cf5113c13f10535d90be54931f8"
+        );
+    }
+
+    #[test]
+    fn unpack() {
+        let bytes = essence().to_bytes().unwrap();
+        assert_eq!(bytes.len(), essence().packed_len());
+        assert_eq!(
+            RegularTransactionEssence::unpack(&mut &bytes[..]).unwrap(),
+            essence()
+        );
+    }
+
+    #[test]
+    fn same_bytes_as_bee_block() {
+        use bee_block::address::{Address as BeeAddress, Ed25519Address};
+        use bee_block::input::{Input, UtxoInput as BeeUtxoInput};
+        use bee_block::output::unlock_condition::{AddressUnlockCondition, UnlockCondition};
+        use bee_block::output::{BasicOutputBuilder, InputsCommitment, Output};
+        use bee_block::payload::transaction::{
+            RegularTransactionEssenceBuilder, TransactionEssence, TransactionId,
+        };
+        use packable::PackableExt;
+
+        let essence = essence();
+        let mut builder = RegularTransactionEssenceBuilder::new(
+            essence.network_id,
+            InputsCommitment::from(essence.inputs_commitment),
+        );
+        for input in &essence.inputs {
+            builder = builder.add_input(Input::Utxo(
+                BeeUtxoInput::new(
+                    TransactionId::from(input.transaction_id),
+                    input.output_index,
+                )
+                .unwrap(),
+            ));
+        }
+        for output in &essence.outputs {
+            let address = match output.address() {
+                Some(Address::Ed25519(address)) => *address,
+                _ => unreachable!("Ed25519 outputs only"),
+            };
+            let output = BasicOutputBuilder::new_with_amount(output.amount)
+                .unwrap()
+                .add_unlock_condition(UnlockCondition::Address(AddressUnlockCondition::new(
+                    BeeAddress::Ed25519(Ed25519Address::new(address)),
+                )))
+                .finish()
+                .unwrap();
+            builder = builder.add_output(Output::Basic(output));
+        }
+        let bee_bytes = TransactionEssence::from(builder.finish().unwrap()).pack_to_vec();
+
+        assert_eq!(essence.to_bytes().unwrap(), bee_bytes);
+        assert_eq!(hash::essence_hash(&bee_bytes), essence.hash().unwrap());
+    }
+}
@@ -0,0 +1,323 @@
+use crate::api::packable::{Error as PackableError, Packable, Read, Write};
+
+use super::{
+    pack_bytes_u16, pack_bytes_u8, pack_vec_u8, read_array, unpack_bytes_u16, unpack_bytes_u8,
+    unpack_vec_u8,
+};
+
+pub const BASIC_OUTPUT_TYPE: u8 = 3;
+
+pub const ED25519_ADDRESS_TYPE: u8 = 0;
+pub const ALIAS_ADDRESS_TYPE: u8 = 8;
+pub const NFT_ADDRESS_TYPE: u8 = 16;
+
+pub const ADDRESS_UNLOCK_CONDITION_TYPE: u8 = 0;
+pub const STORAGE_DEPOSIT_RETURN_UNLOCK_CONDITION_TYPE: u8 = 1;
+pub const TIMELOCK_UNLOCK_CONDITION_TYPE: u8 = 2;
+pub const EXPIRATION_UNLOCK_CONDITION_TYPE: u8 = 3;
+
+pub const SENDER_FEATURE_TYPE: u8 = 0;
+pub const METADATA_FEATURE_TYPE: u8 = 2;
+pub const TAG_FEATURE_TYPE: u8 = 3;
+
+pub const TOKEN_ID_LENGTH: usize = 38;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Address {
+    Ed25519([u8; 32]),
+    Alias([u8; 32]),
+    Nft([u8; 32]),
+}
+
+impl Packable for Address {
+    fn packed_len(&self) -> usize {
+        1 + 32
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        let (address_type, address) = match self {
+            Address::Ed25519(address) => (ED25519_ADDRESS_TYPE, address),
+            Address::Alias(address) => (ALIAS_ADDRESS_TYPE, address),
+            Address::Nft(address) => (NFT_ADDRESS_TYPE, address),
+        };
+        address_type.pack(buf)?;
+        buf.write_all(address)?;
+        Ok(())
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        Ok(match u8::unpack(buf)? {
+            ED25519_ADDRESS_TYPE => Address::Ed25519(read_array(buf)?),
+            ALIAS_ADDRESS_TYPE => Address::Alias(read_array(buf)?),
+            NFT_ADDRESS_TYPE => Address::Nft(read_array(buf)?),
+            _ => return Err(PackableError::InvalidType),
+        })
+    }
+}
+
+/// Native token with its amount (uint256, little endian)
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct NativeToken {
+    pub token_id: [u8; TOKEN_ID_LENGTH],
+    pub amount: [u8; 32],
+}
+
+impl NativeToken {
+    pub fn new(token_id: [u8; TOKEN_ID_LENGTH], amount: u128) -> Self {
+        let mut le = [0u8; 32];
+        le[..16].copy_from_slice(&amount.to_le_bytes());
+        Self {
+            token_id,
+            amount: le,
+        }
+    }
+}
+
+impl Packable for NativeToken {
+    fn packed_len(&self) -> usize {
+        TOKEN_ID_LENGTH + 32
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        buf.write_all(&self.token_id)?;
+        buf.write_all(&self.amount)?;
+        Ok(())
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            token_id: read_array(buf)?,
+            amount: read_array(buf)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum UnlockCondition {
+    Address(Address),
+    StorageDepositReturn {
+        return_address: Address,
+        amount: u64,
+    },
+    Timelock {
+        unix_time: u32,
+    },
+    Expiration {
+        return_address: Address,
+        unix_time: u32,
+    },
+}
+
+impl UnlockCondition {
+    pub fn kind(&self) -> u8 {
+        match self {
+            UnlockCondition::Address(_) => ADDRESS_UNLOCK_CONDITION_TYPE,
+            UnlockCondition::StorageDepositReturn { .. } => {
+                STORAGE_DEPOSIT_RETURN_UNLOCK_CONDITION_TYPE
+            }
+            UnlockCondition::Timelock { .. } => TIMELOCK_UNLOCK_CONDITION_TYPE,
+            UnlockCondition::Expiration { .. } => EXPIRATION_UNLOCK_CONDITION_TYPE,
+        }
+    }
+}
+
+impl Packable for UnlockCondition {
+    fn packed_len(&self) -> usize {
+        1 + match self {
+            UnlockCondition::Address(address) => address.packed_len(),
+            UnlockCondition::StorageDepositReturn { return_address, .. } => {
+                return_address.packed_len() + 8
+            }
+            UnlockCondition::Timelock { .. } => 4,
+            UnlockCondition::Expiration { return_address, .. } => return_address.packed_len() + 4,
+        }
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        self.kind().pack(buf)?;
+        match self {
+            UnlockCondition::Address(address) => address.pack(buf),
+            UnlockCondition::StorageDepositReturn {
+                return_address,
+                amount,
+            } => {
+                return_address.pack(buf)?;
+                amount.pack(buf)
+            }
+            UnlockCondition::Timelock { unix_time } => unix_time.pack(buf),
+            UnlockCondition::Expiration {
+                return_address,
+                unix_time,
+            } => {
+                return_address.pack(buf)?;
+                unix_time.pack(buf)
+            }
+        }
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        Ok(match u8::unpack(buf)? {
+            ADDRESS_UNLOCK_CONDITION_TYPE => UnlockCondition::Address(Address::unpack(buf)?),
+            STORAGE_DEPOSIT_RETURN_UNLOCK_CONDITION_TYPE => UnlockCondition::StorageDepositReturn {
+                return_address: Address::unpack(buf)?,
+                amount: u64::unpack(buf)?,
+            },
+            TIMELOCK_UNLOCK_CONDITION_TYPE => UnlockCondition::Timelock {
+                unix_time: u32::unpack(buf)?,
+            },
+            EXPIRATION_UNLOCK_CONDITION_TYPE => UnlockCondition::Expiration {
+                return_address: Address::unpack(buf)?,
+                unix_time: u32::unpack(buf)?,
+            },
+            _ => return Err(PackableError::InvalidType),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum Feature {
+    Sender(Address),
+    /// up to 8192 bytes
+    Metadata(Vec<u8>),
+    /// up to 64 bytes
+    Tag(Vec<u8>),
+}
+
+impl Packable for Feature {
+    fn packed_len(&self) -> usize {
+        1 + match self {
+            Feature::Sender(address) => address.packed_len(),
+            Feature::Metadata(data) => 2 + data.len(),
+            Feature::Tag(tag) => 1 + tag.len(),
+        }
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        match self {
+            Feature::Sender(address) => {
+                SENDER_FEATURE_TYPE.pack(buf)?;
+                address.pack(buf)
+            }
+            Feature::Metadata(data) => {
+                METADATA_FEATURE_TYPE.pack(buf)?;
+                pack_bytes_u16(data, buf)
+            }
+            Feature::Tag(tag) => {
+                TAG_FEATURE_TYPE.pack(buf)?;
+                pack_bytes_u8(tag, buf)
+            }
+        }
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        Ok(match u8::unpack(buf)? {
+            SENDER_FEATURE_TYPE => Feature::Sender(Address::unpack(buf)?),
+            METADATA_FEATURE_TYPE => Feature::Metadata(unpack_bytes_u16(buf)?),
+            TAG_FEATURE_TYPE => Feature::Tag(unpack_bytes_u8(buf)?),
+            _ => return Err(PackableError::InvalidType),
+        })
+    }
+}
+
+/// Basic output (the only output type the app signs)
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct BasicOutput {
+    pub amount: u64,
+    pub native_tokens: Vec<NativeToken>,
+    pub unlock_conditions: Vec<UnlockCondition>,
+    pub features: Vec<Feature>,
+}
+
+impl BasicOutput {
+    /// Output of `amount` that is unlocked by `address` only
+    pub fn new(amount: u64, address: Address) -> Self {
+        Self {
+            amount,
+            native_tokens: Vec::new(),
+            unlock_conditions: vec![UnlockCondition::Address(address)],
+            features: Vec::new(),
+        }
+    }
+
+    pub fn with_native_token(mut self, native_token: NativeToken) -> Self {
+        self.native_tokens.push(native_token);
+        self
+    }
+
+    pub fn with_unlock_condition(mut self, unlock_condition: UnlockCondition) -> Self {
+        self.unlock_conditions.push(unlock_condition);
+        self
+    }
+
+    pub fn with_feature(mut self, feature: Feature) -> Self {
+        self.features.push(feature);
+        self
+    }
+
+    /// Address of the address unlock condition
+    pub fn address(&self) -> Option<&Address> {
+        self.unlock_conditions.iter().find_map(|u| match u {
+            UnlockCondition::Address(address) => Some(address),
+            _ => None,
+        })
+    }
+}
+
+impl Packable for BasicOutput {
+    fn packed_len(&self) -> usize {
+        1 + 8
+            + 1
+            + self
+                .native_tokens
+                .iter()
+                .map(Packable::packed_len)
+                .sum::<usize>()
+            + 1
+            + self
+                .unlock_conditions
+                .iter()
+                .map(Packable::packed_len)
+                .sum::<usize>()
+            + 1
+            + self
+                .features
+                .iter()
+                .map(Packable::packed_len)
+                .sum::<usize>()
+    }
+
+    fn pack<W: Write>(&self, buf: &mut W) -> Result<(), PackableError> {
+        BASIC_OUTPUT_TYPE.pack(buf)?;
+        self.amount.pack(buf)?;
+        pack_vec_u8(&self.native_tokens, buf)?;
+        pack_vec_u8(&self.unlock_conditions, buf)?;
+        pack_vec_u8(&self.features, buf)
+    }
+
+    fn unpack<R: Read>(buf: &mut R) -> Result<Self, PackableError>
+    where
+        Self: Sized,
+    {
+        if u8::unpack(buf)? != BASIC_OUTPUT_TYPE {
+            return Err(PackableError::InvalidType);
+        }
+        Ok(Self {
+            amount: u64::unpack(buf)?,
+            native_tokens: unpack_vec_u8(buf)?,
+            unlock_conditions: unpack_vec_u8(buf)?,
+            features: unpack_vec_u8(buf)?,
+        })
+    }
+}
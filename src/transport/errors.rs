@@ -1,14 +1,107 @@
 use thiserror::Error;
 
+pub use crate::ledger::ledger_transport_hid::framing::FramingError;
+pub use crate::ledger::ledger_transport_hid::LedgerHIDError;
+
 #[derive(Error, Debug)]
 pub enum LedgerTCPError {
-    /// Device not found error
-    #[error("Ledger connect error")]
-    ConnectError,
+    /// endpoint can't be resolved or connected
+    #[error("Ledger connect error: {0}")]
+    ConnectError(#[source] std::io::Error),
+    /// nothing is listening on the endpoint (e.g. simulator not running)
+    #[error("TCP connection refused")]
+    ConnectionRefused,
     /// zemu reponse error
     #[error("TCP response error")]
     ResponseError,
+    /// connection was closed by the other side and couldn't be reestablished
+    #[error("TCP connection lost")]
+    ConnectionLost,
+    /// no answer within the configured read/write timeout
+    #[error("TCP timeout")]
+    Timeout,
+    /// payload doesn't fit into the APDU length
+    #[error("TCP: APDU command too long")]
+    CommandTooLong,
+    /// Inner error
+    #[error("Ledger inner error: {0}")]
+    InnerError(#[source] std::io::Error),
+}
+
+#[cfg(feature = "speculos-http")]
+#[derive(Error, Debug)]
+pub enum LedgerHTTPError {
+    /// Speculos API not reachable
+    #[error("HTTP connect error: {0}")]
+    ConnectError(#[source] Box<ureq::Transport>),
+    /// no answer within the configured timeout
+    #[error("HTTP timeout")]
+    Timeout,
+    /// payload doesn't fit into the APDU length
+    #[error("HTTP: APDU command too long")]
+    CommandTooLong,
+    /// Speculos answered with an error status
+    #[error("HTTP status {0}")]
+    StatusError(u16),
+    /// body is not the expected json
+    #[error("HTTP response error")]
+    ResponseError,
     /// Inner error
-    #[error("Ledger inner error")]
-    InnerError,
+    #[error("HTTP inner error: {0}")]
+    InnerError(#[source] Box<ureq::Transport>),
+    /// i/o error of the async transport, including failed connects
+    #[error("HTTP i/o error: {0}")]
+    Io(#[source] std::io::Error),
+}
+
+#[cfg(feature = "speculos-http")]
+impl From<ureq::Error> for LedgerHTTPError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(status, _) => LedgerHTTPError::StatusError(status),
+            ureq::Error::Transport(t) => match t.kind() {
+                ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed => {
+                    LedgerHTTPError::ConnectError(Box::new(t))
+                }
+                ureq::ErrorKind::Io => match std::error::Error::source(&t)
+                    .and_then(|e| e.downcast_ref::<std::io::Error>())
+                    .map(|e| e.kind())
+                {
+                    Some(std::io::ErrorKind::TimedOut) | Some(std::io::ErrorKind::WouldBlock) => {
+                        LedgerHTTPError::Timeout
+                    }
+                    _ => LedgerHTTPError::InnerError(Box::new(t)),
+                },
+                _ => LedgerHTTPError::InnerError(Box::new(t)),
+            },
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum LedgerMockError {
+    /// command that wasn't scripted (hex)
+    #[error("Mock: unexpected command {0}")]
+    UnexpectedCommand(String),
+    /// command doesn't match the scripted one (hex)
+    #[error("Mock: exchange {index}: expected command {expected} but got {got}")]
+    Mismatch {
+        index: usize,
+        expected: String,
+        got: String,
+    },
+    /// scripted commands that were never sent
+    #[error("Mock: {0} expectation(s) not consumed")]
+    Unconsumed(usize),
+    /// failure returned by a handler
+    #[error("Mock: {0}")]
+    Failure(String),
+    /// simulated communication error, retried like the ones of HID (see [RetryClass::Comm])
+    ///
+    /// [RetryClass::Comm]: crate::transport::RetryClass::Comm
+    #[error("Mock: communication error: {0}")]
+    Comm(String),
+    /// transcript can't be read or parsed
+    #[error("Mock: invalid transcript: {0}")]
+    InvalidTranscript(String),
 }
@@ -0,0 +1,272 @@
+use crate::APIError;
+
+use lazy_static::lazy_static;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+lazy_static! {
+    static ref DEVICE_LOCKS: DeviceLocks = DeviceLocks::default();
+}
+
+#[derive(Default)]
+struct DeviceLockState {
+    locked: bool,
+    // waiting tickets, served first come first served
+    queue: VecDeque<u64>,
+}
+
+#[derive(Default)]
+struct DeviceLocks {
+    states: Mutex<HashMap<String, DeviceLockState>>,
+    released: Condvar,
+    // wakes the waiters of `lock_async`
+    #[cfg(feature = "async")]
+    released_async: tokio::sync::Notify,
+    next_ticket: AtomicU64,
+}
+
+impl DeviceLocks {
+    fn notify(&self) {
+        self.released.notify_all();
+        #[cfg(feature = "async")]
+        self.released_async.notify_waiters();
+    }
+
+    fn enqueue(&self, key: &str) -> u64 {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        let mut states = self.states.lock().expect("device locks poisoned");
+        states
+            .entry(key.to_string())
+            .or_default()
+            .queue
+            .push_back(ticket);
+        ticket
+    }
+
+    // takes the lock if it's free and `ticket` is the first in the queue
+    fn try_acquire(
+        states: &mut HashMap<String, DeviceLockState>,
+        key: &str,
+        ticket: u64,
+    ) -> Option<DeviceLockGuard> {
+        let state = states.get_mut(key).expect("device lock state vanished");
+        if state.locked || state.queue.front() != Some(&ticket) {
+            return None;
+        }
+        state.queue.pop_front();
+        state.locked = true;
+        debug!("device lock {} acquired", key);
+        Some(DeviceLockGuard {
+            key: key.to_string(),
+        })
+    }
+
+    // removes a waiter that gave up
+    fn leave(&self, states: &mut HashMap<String, DeviceLockState>, key: &str, ticket: u64) {
+        if let Some(state) = states.get_mut(key) {
+            state.queue.retain(|t| *t != ticket);
+            if !state.locked && state.queue.is_empty() {
+                states.remove(key);
+            }
+        }
+        // the next one in the queue may be able to go now
+        self.notify();
+    }
+}
+
+/// Exclusive access to one device (or simulator endpoint), released on drop
+pub(crate) struct DeviceLockGuard {
+    key: String,
+}
+
+impl Drop for DeviceLockGuard {
+    fn drop(&mut self) {
+        let mut states = DEVICE_LOCKS.states.lock().expect("device locks poisoned");
+        if let Some(state) = states.get_mut(&self.key) {
+            state.locked = false;
+            if state.queue.is_empty() {
+                states.remove(&self.key);
+            }
+        }
+        DEVICE_LOCKS.notify();
+        debug!("device lock {} released", self.key);
+    }
+}
+
+/// Waits up to `timeout` for the lock of `key`
+///
+/// Waiters are served in the order they arrived.
+pub(crate) fn lock(key: &str, timeout: Duration) -> Result<DeviceLockGuard, APIError> {
+    let deadline = Instant::now() + timeout;
+    let ticket = DEVICE_LOCKS.enqueue(key);
+
+    debug!("waiting for device lock {}", key);
+    let mut states = DEVICE_LOCKS.states.lock().expect("device locks poisoned");
+    loop {
+        if let Some(guard) = DeviceLocks::try_acquire(&mut states, key, ticket) {
+            return Ok(guard);
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            DEVICE_LOCKS.leave(&mut states, key, ticket);
+            return Err(APIError::Timeout);
+        }
+
+        states = DEVICE_LOCKS
+            .released
+            .wait_timeout(states, deadline - now)
+            .expect("device locks poisoned")
+            .0;
+    }
+}
+
+/// Like [lock] without blocking the thread while waiting
+///
+/// Dropping the future gives up the place in the queue.
+#[cfg(feature = "async")]
+pub(crate) async fn lock_async(key: &str, timeout: Duration) -> Result<DeviceLockGuard, APIError> {
+    // gives up the place in the queue unless the lock was acquired
+    struct Waiter<'a> {
+        key: &'a str,
+        ticket: u64,
+        acquired: bool,
+    }
+
+    impl Drop for Waiter<'_> {
+        fn drop(&mut self) {
+            if !self.acquired {
+                let mut states = DEVICE_LOCKS.states.lock().expect("device locks poisoned");
+                DEVICE_LOCKS.leave(&mut states, self.key, self.ticket);
+            }
+        }
+    }
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut waiter = Waiter {
+        key,
+        ticket: DEVICE_LOCKS.enqueue(key),
+        acquired: false,
+    };
+
+    debug!("waiting for device lock {}", key);
+    loop {
+        // registered before the state is checked so no release is missed
+        let mut released = std::pin::pin!(DEVICE_LOCKS.released_async.notified());
+        released.as_mut().enable();
+
+        {
+            let mut states = DEVICE_LOCKS.states.lock().expect("device locks poisoned");
+            if let Some(guard) = DeviceLocks::try_acquire(&mut states, key, waiter.ticket) {
+                waiter.acquired = true;
+                return Ok(guard);
+            }
+        }
+
+        if tokio::time::timeout_at(deadline, released).await.is_err() {
+            return Err(APIError::Timeout);
+        }
+    }
+}
+
+/// Gets the lock of `key` only if it's free and nobody else is waiting for it
+pub(crate) fn try_lock(key: &str) -> Result<DeviceLockGuard, APIError> {
+    let mut states = DEVICE_LOCKS.states.lock().expect("device locks poisoned");
+    let state = states.entry(key.to_string()).or_default();
+    if state.locked || !state.queue.is_empty() {
+        return Err(APIError::TransportBusy);
+    }
+    state.locked = true;
+    debug!("device lock {} acquired", key);
+    Ok(DeviceLockGuard {
+        key: key.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    fn is_registered(key: &str) -> bool {
+        DEVICE_LOCKS.states.lock().unwrap().contains_key(key)
+    }
+
+    fn waiting(key: &str) -> usize {
+        DEVICE_LOCKS
+            .states
+            .lock()
+            .unwrap()
+            .get(key)
+            .map_or(0, |state| state.queue.len())
+    }
+
+    #[test]
+    fn fifo() {
+        const KEY: &str = "test://fifo";
+        let guard = lock(KEY, Duration::from_secs(1)).unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for i in 0..5 {
+            let order = order.clone();
+            waiters.push(thread::spawn(move || {
+                let _guard = lock(KEY, Duration::from_secs(10)).unwrap();
+                order.lock().unwrap().push(i);
+            }));
+            // queue the waiters in a known order
+            while waiting(KEY) != i + 1 {
+                thread::yield_now();
+            }
+        }
+
+        assert!(matches!(try_lock(KEY), Err(APIError::TransportBusy)));
+        drop(guard);
+        waiters.into_iter().for_each(|w| w.join().unwrap());
+
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+        assert!(!is_registered(KEY));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn lock_async_waits_and_leaves_queue_when_dropped() {
+        const KEY: &str = "test://lock_async";
+        let guard = lock(KEY, Duration::from_secs(1)).unwrap();
+
+        // the dropped waiter doesn't keep its place in the queue
+        let waiter = lock_async(KEY, Duration::from_secs(10));
+        assert!(tokio::time::timeout(Duration::from_millis(10), waiter)
+            .await
+            .is_err());
+        assert_eq!(waiting(KEY), 0);
+
+        let released = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(guard);
+        });
+        let guard = lock_async(KEY, Duration::from_secs(10)).await.unwrap();
+        released.join().unwrap();
+        drop(guard);
+        assert!(!is_registered(KEY));
+    }
+
+    #[test]
+    fn timeout() {
+        const KEY: &str = "test://timeout";
+        let guard = lock(KEY, Duration::from_secs(1)).unwrap();
+        assert!(matches!(
+            lock(KEY, Duration::from_millis(10)),
+            Err(APIError::Timeout)
+        ));
+        assert_eq!(waiting(KEY), 0);
+        drop(guard);
+        assert!(!is_registered(KEY));
+    }
+}
@@ -1,101 +1,788 @@
+//! Transports to a device (HID) or the Speculos simulator (TCP, HTTP)
+//!
+//! The simulator endpoint defaults to `127.0.0.1:9999` and can be changed with
+//! `LEDGER_SIMULATOR_HOST`/`LEDGER_SIMULATOR_PORT` ([TCPConfig::from_env]) or in code. Setups
+//! that only expose the REST API are used with `TransportTypes::HTTP` (feature `speculos-http`).
+//! If several devices are attached, [list_devices] returns them and [HIDSelector] picks one.
+//!
+//! HID exchanges wait 30 seconds for an answer by default, see [Transport::set_read_timeout].
+//! A running exchange can be aborted with [Transport::canceller]. Payloads over 255 bytes fail
+//! with `APIError::CommandTooLong` unless [APDUEncoding::Extended] is set.
+//!
+//! Transport failures keep their cause (`APIError::HID`, `TCP`, `HTTP`, `Mock`), errors of
+//! other [Exchange] implementations end up in `APIError::Transport`.
+
 pub mod errors;
+pub(crate) mod lock;
+pub(crate) mod observer;
+mod retry;
+pub mod transcript;
 
-use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand};
-use crate::ledger::ledger_transport_hid::TransportNativeHID;
-use crate::ledger::ledger_transport_tcp::{Callback, TransportTCP};
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand, APDUEncoding};
+pub use crate::ledger::ledger_transport_hid::framing as hid_framing;
+pub use crate::ledger::ledger_transport_hid::{ExchangeCanceller, HIDSelector, LedgerDeviceInfo};
+use crate::ledger::ledger_transport_hid::{LedgerHIDError, TransportNativeHID};
+#[cfg(feature = "speculos-http")]
+use crate::ledger::ledger_transport_http::TransportHTTP;
+pub use crate::ledger::ledger_transport_mock::{MockHandler, TransportMock};
+use crate::ledger::ledger_transport_tcp::TransportTCP;
 use crate::APIError;
 
-use lazy_static::lazy_static;
+pub use crate::ledger::ledger_transport::Exchange;
+pub use observer::{Callback, Observer, TransportEvent};
+pub use retry::{RetryClass, RetryPolicy};
+pub use transcript::{Transcript, TranscriptFormat};
+
+#[cfg(feature = "speculos-http")]
+use errors::LedgerHTTPError;
+use errors::LedgerTCPError;
+use lock::DeviceLockGuard;
+use observer::CallbackObserver;
+
+use std::ops::Deref;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, warn};
+
+pub(crate) const TRANSPORT_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SIMULATOR_DEFAULT_HOST: &str = "127.0.0.1";
+const SIMULATOR_DEFAULT_PORT: u16 = 9999;
+#[cfg(feature = "speculos-http")]
+const SIMULATOR_DEFAULT_API_PORT: u16 = 5000;
 
-use std::time::{Duration, Instant};
+const SIMULATOR_DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SIMULATOR_HOST_ENV: &str = "LEDGER_SIMULATOR_HOST";
+const SIMULATOR_PORT_ENV: &str = "LEDGER_SIMULATOR_PORT";
+#[cfg(feature = "speculos-http")]
+const SIMULATOR_API_PORT_ENV: &str = "LEDGER_SIMULATOR_API_PORT";
+
+fn env_host(default: &str) -> String {
+    std::env::var(SIMULATOR_HOST_ENV).unwrap_or_else(|_| String::from(default))
+}
+
+fn env_port(name: &str, default: u16) -> u16 {
+    match std::env::var(name) {
+        Ok(port) => port.parse::<u16>().unwrap_or_else(|_| {
+            warn!("invalid {}: {}", name, port);
+            default
+        }),
+        Err(_) => default,
+    }
+}
 
-use log::debug;
+/// Endpoint of a Speculos simulator (APDU port)
+///
+/// `None` timeouts block forever. The read timeout is unset by default because
+/// commands like `user_confirm` wait for buttons to be pressed.
+///
+/// Transports to the same host and port are used one after another. Transports to different
+/// endpoints of one simulator (APDU port and REST API) only wait for each other if they have
+/// the same `instance`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TCPConfig {
+    pub host: String,
+    pub port: u16,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub write_timeout: Option<Duration>,
+    /// identifies the simulator for the transport lock instead of host and port
+    pub instance: Option<String>,
+}
 
-lazy_static! {
-    static ref TRANSPORT_MUTEX: Arc<Mutex<i32>> = Arc::new(Mutex::new(0));
+impl Default for TCPConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from(SIMULATOR_DEFAULT_HOST),
+            port: SIMULATOR_DEFAULT_PORT,
+            connect_timeout: Some(SIMULATOR_DEFAULT_CONNECT_TIMEOUT),
+            read_timeout: None,
+            write_timeout: None,
+            instance: None,
+        }
+    }
 }
 
-use std::sync::MutexGuard;
+impl TCPConfig {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: String::from(host),
+            port,
+            ..Default::default()
+        }
+    }
+
+    /// Reads host and port from `LEDGER_SIMULATOR_HOST` and `LEDGER_SIMULATOR_PORT`
+    ///
+    /// Variables that are not set (or can't be parsed) fall back to `127.0.0.1:9999`.
+    pub fn from_env() -> Self {
+        Self {
+            host: env_host(SIMULATOR_DEFAULT_HOST),
+            port: env_port(SIMULATOR_PORT_ENV, SIMULATOR_DEFAULT_PORT),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = String::from(host);
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn with_write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Shares the transport lock with other configs of the same simulator instance
+    pub fn with_instance(mut self, instance: &str) -> Self {
+        self.instance = Some(String::from(instance));
+        self
+    }
+
+    pub(crate) fn lock_key(&self) -> String {
+        speculos_lock_key(self.instance.as_deref(), &self.host, self.port)
+    }
+}
+
+/// Endpoint of the Speculos REST API (feature `speculos-http`)
+///
+/// Like with [TCPConfig] the read timeout is unset by default, and the transport lock is
+/// per host and port unless `instance` is set.
+#[cfg(feature = "speculos-http")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HTTPConfig {
+    pub host: String,
+    pub port: u16,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    /// identifies the simulator for the transport lock instead of host and port
+    pub instance: Option<String>,
+}
+
+#[cfg(feature = "speculos-http")]
+impl Default for HTTPConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from(SIMULATOR_DEFAULT_HOST),
+            port: SIMULATOR_DEFAULT_API_PORT,
+            connect_timeout: Some(SIMULATOR_DEFAULT_CONNECT_TIMEOUT),
+            read_timeout: None,
+            instance: None,
+        }
+    }
+}
+
+#[cfg(feature = "speculos-http")]
+impl HTTPConfig {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: String::from(host),
+            port,
+            ..Default::default()
+        }
+    }
+
+    /// Reads host and port from `LEDGER_SIMULATOR_HOST` and `LEDGER_SIMULATOR_API_PORT`
+    ///
+    /// Variables that are not set (or can't be parsed) fall back to `127.0.0.1:5000`.
+    pub fn from_env() -> Self {
+        Self {
+            host: env_host(SIMULATOR_DEFAULT_HOST),
+            port: env_port(SIMULATOR_API_PORT_ENV, SIMULATOR_DEFAULT_API_PORT),
+            ..Default::default()
+        }
+    }
 
-#[derive(Copy, Clone)]
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = String::from(host);
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn with_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Shares the transport lock with other configs of the same simulator instance
+    pub fn with_instance(mut self, instance: &str) -> Self {
+        self.instance = Some(String::from(instance));
+        self
+    }
+
+    pub(crate) fn lock_key(&self) -> String {
+        speculos_lock_key(self.instance.as_deref(), &self.host, self.port)
+    }
+
+    pub(crate) fn base_url(&self) -> String {
+        format!("http://{}:{}", self.host, self.port)
+    }
+
+    pub(crate) fn agent(&self) -> ureq::Agent {
+        let mut builder = ureq::AgentBuilder::new();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.timeout_connect(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.timeout_read(timeout);
+        }
+        builder.build()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum TransportTypes {
-    TCP,
-    NativeHID,
+    TCP(TCPConfig),
+    #[cfg(feature = "speculos-http")]
+    HTTP(HTTPConfig),
+    NativeHID(HIDSelector),
+    /// scripted in-process transport for tests
+    Mock(TransportMock),
 }
 
 pub struct Transport {
     pub transport: LedgerTransport,
-    _transport_mutex: MutexGuard<'static, i32>,
+    transport_type: TransportTypes,
+    observers: Vec<Arc<dyn Observer>>,
+    retry_policy: Mutex<Option<RetryPolicy>>,
+    // declared last so the device is closed before the lock is released
+    _lock: DeviceLockGuard,
+}
+
+impl Transport {
+    pub fn transport_type(&self) -> TransportTypes {
+        self.transport_type.clone()
+    }
+
+    /// Registers an observer that is notified about every exchange of this transport
+    pub fn add_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Sets how long exchanges wait for an answer, `None` waits forever
+    ///
+    /// HID transports default to 30 seconds, TCP and HTTP to the read timeout of their config.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        match &self.transport {
+            LedgerTransport::TCP(t) => t.set_read_timeout(timeout),
+            #[cfg(feature = "speculos-http")]
+            LedgerTransport::HTTP(t) => t.set_read_timeout(timeout),
+            LedgerTransport::NativeHID(h) => h.set_read_timeout(timeout),
+            LedgerTransport::Mock(_) => {}
+        }
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.transport.read_timeout()
+    }
+
+    /// Sets how commands are serialized, [APDUEncoding::Extended] only works with apps that
+    /// accept extended length APDUs
+    pub fn set_apdu_encoding(&self, encoding: APDUEncoding) {
+        match &self.transport {
+            LedgerTransport::TCP(t) => t.set_apdu_encoding(encoding),
+            #[cfg(feature = "speculos-http")]
+            LedgerTransport::HTTP(t) => t.set_apdu_encoding(encoding),
+            LedgerTransport::NativeHID(h) => h.set_apdu_encoding(encoding),
+            LedgerTransport::Mock(_) => {}
+        }
+    }
+
+    /// Sets the policy for retrying failed exchanges, `None` (default) doesn't retry
+    pub fn set_retry_policy(&self, policy: Option<RetryPolicy>) {
+        *self.retry_policy.lock().expect("retry policy poisoned") = policy;
+    }
+
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy
+            .lock()
+            .expect("retry policy poisoned")
+            .clone()
+    }
+
+    /// Handle to cancel a running exchange from another thread (HID only)
+    ///
+    /// The cancelled exchange fails with `APIError::Cancelled`.
+    pub fn canceller(&self) -> Option<ExchangeCanceller> {
+        match &self.transport {
+            LedgerTransport::NativeHID(h) => Some(h.canceller()),
+            _ => None,
+        }
+    }
+
+    /// Like [Exchange::exchange] with a read timeout for this call only
+    ///
+    /// Fails with `APIError::Timeout` if there was no answer in time.
+    pub fn exchange_with_timeout<I>(
+        &self,
+        command: &APDUCommand<I>,
+        timeout: Option<Duration>,
+    ) -> Result<APDUAnswer<Vec<u8>>, APIError>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        let command = owned_command(command);
+        let policy = match self.retry_policy() {
+            Some(policy) if RetryPolicy::is_repeatable(&command) => policy,
+            _ => {
+                return self
+                    .transport
+                    .observed_exchange(&command, timeout, &self.observers)
+            }
+        };
+
+        let mut attempt = 1;
+        loop {
+            match self
+                .transport
+                .observed_exchange(&command, timeout, &self.observers)
+            {
+                Err(e) if policy.should_retry(&e, attempt) => {
+                    debug!("retrying command {:02x}: {}", command.ins, e);
+                    std::thread::sleep(policy.backoff(attempt));
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
 }
 
-impl Drop for Transport {
-    fn drop(&mut self) {
-        debug!("transport_mutex released");
+// observers get an owned command
+pub(crate) fn owned_command<I: Deref<Target = [u8]>>(
+    command: &APDUCommand<I>,
+) -> APDUCommand<Vec<u8>> {
+    APDUCommand {
+        cla: command.cla,
+        ins: command.ins,
+        p1: command.p1,
+        p2: command.p2,
+        data: command.data.to_vec(),
+    }
+}
+
+impl Exchange for Transport {
+    type Error = APIError;
+    type AnswerType = Vec<u8>;
+
+    fn exchange<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.exchange_with_timeout(command, self.transport.read_timeout())
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
+        self.transport.apdu_encoding()
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy()
+    }
+
+    fn exchange_once<I>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.transport.observed_exchange(
+            &owned_command(command),
+            self.transport.read_timeout(),
+            &self.observers,
+        )
     }
 }
 
 #[allow(clippy::upper_case_acronyms)]
 pub enum LedgerTransport {
     TCP(TransportTCP),
+    #[cfg(feature = "speculos-http")]
+    HTTP(TransportHTTP),
     NativeHID(TransportNativeHID),
+    Mock(TransportMock),
 }
 
-impl LedgerTransport {
-    pub(crate) fn exchange(
+impl Exchange for LedgerTransport {
+    type Error = APIError;
+    type AnswerType = Vec<u8>;
+
+    fn exchange<I>(
         &self,
-        apdu_command: &APDUCommand<Vec<u8>>,
-    ) -> Result<APDUAnswer<Vec<u8>>, APIError> {
+        apdu_command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Self::AnswerType>, Self::Error>
+    where
+        I: Deref<Target = [u8]> + Send + Sync,
+    {
+        self.observed_exchange(&owned_command(apdu_command), self.read_timeout(), &[])
+    }
+
+    fn apdu_encoding(&self) -> APDUEncoding {
         match self {
-            LedgerTransport::TCP(t) => t
-                .exchange(apdu_command)
-                .map_err(|_| APIError::TransportError),
-            LedgerTransport::NativeHID(h) => h
-                .exchange(apdu_command)
-                .map_err(|_| APIError::TransportError),
+            LedgerTransport::TCP(t) => t.apdu_encoding(),
+            #[cfg(feature = "speculos-http")]
+            LedgerTransport::HTTP(t) => t.apdu_encoding(),
+            LedgerTransport::NativeHID(h) => h.apdu_encoding(),
+            LedgerTransport::Mock(m) => m.apdu_encoding(),
         }
     }
 }
 
-fn try_get_lock(timeout: Duration) -> Result<MutexGuard<'static, i32>, APIError> {
-    let start_time = Instant::now();
-    while start_time.elapsed() < timeout {
-        match TRANSPORT_MUTEX.try_lock() {
-            Ok(guard) => {
-                return Ok(guard);
+impl LedgerTransport {
+    fn read_timeout(&self) -> Option<Duration> {
+        match self {
+            LedgerTransport::TCP(t) => t.read_timeout(),
+            #[cfg(feature = "speculos-http")]
+            LedgerTransport::HTTP(t) => t.read_timeout(),
+            LedgerTransport::NativeHID(h) => h.read_timeout(),
+            LedgerTransport::Mock(_) => None,
+        }
+    }
+
+    fn observed_exchange(
+        &self,
+        command: &APDUCommand<Vec<u8>>,
+        timeout: Option<Duration>,
+        observers: &[Arc<dyn Observer>],
+    ) -> Result<APDUAnswer<Vec<u8>>, APIError> {
+        match self {
+            LedgerTransport::TCP(t) => {
+                observe(command, observers, |c| t.exchange_with_timeout(c, timeout))
+                    .map_err(tcp_error)
+            }
+            #[cfg(feature = "speculos-http")]
+            LedgerTransport::HTTP(t) => {
+                observe(command, observers, |c| t.exchange_with_timeout(c, timeout))
+                    .map_err(http_error)
+            }
+            LedgerTransport::NativeHID(h) => {
+                observe(command, observers, |c| h.exchange_with_timeout(c, timeout))
+                    .map_err(hid_error)
             }
-            Err(_) => {
-                debug!("trying to acquire transport_mutex lock...");
+            LedgerTransport::Mock(m) => {
+                observe(command, observers, |c| m.exchange(c)).map_err(APIError::Mock)
             }
         }
-        std::thread::sleep(Duration::from_secs(1));
     }
-    Err(APIError::Timeout)
+}
+
+fn observe<F, E>(
+    command: &APDUCommand<Vec<u8>>,
+    observers: &[Arc<dyn Observer>],
+    exchange: F,
+) -> Result<APDUAnswer<Vec<u8>>, E>
+where
+    F: FnOnce(&APDUCommand<Vec<u8>>) -> Result<APDUAnswer<Vec<u8>>, E>,
+    E: std::error::Error + 'static,
+{
+    observers.iter().for_each(|o| o.on_request(command));
+
+    match exchange(command) {
+        Ok(answer) => {
+            observers.iter().for_each(|o| o.on_answer(command, &answer));
+            Ok(answer)
+        }
+        Err(e) => {
+            observers.iter().for_each(|o| o.on_error(command, &e));
+            Err(e)
+        }
+    }
+}
+
+pub(crate) fn tcp_error(e: LedgerTCPError) -> APIError {
+    match e {
+        LedgerTCPError::Timeout => APIError::Timeout,
+        LedgerTCPError::CommandTooLong => APIError::CommandTooLong,
+        e => APIError::TCP(e),
+    }
+}
+
+#[cfg(feature = "speculos-http")]
+pub(crate) fn http_error(e: LedgerHTTPError) -> APIError {
+    match e {
+        LedgerHTTPError::Timeout => APIError::Timeout,
+        LedgerHTTPError::CommandTooLong => APIError::CommandTooLong,
+        e => APIError::HTTP(e),
+    }
+}
+
+fn hid_error(e: LedgerHIDError) -> APIError {
+    match e {
+        LedgerHIDError::Timeout => APIError::Timeout,
+        LedgerHIDError::Cancelled => APIError::Cancelled,
+        LedgerHIDError::CommandTooLong => APIError::CommandTooLong,
+        e => APIError::HID(e),
+    }
+}
+
+/// List the attached ledger devices
+///
+/// Entries can be used to open a specific device with [HIDSelector::Path] or [HIDSelector::Serial].
+pub fn list_devices() -> Result<Vec<LedgerDeviceInfo>, APIError> {
+    let api = hidapi::HidApi::new().map_err(|e| APIError::HID(e.into()))?;
+    Ok(TransportNativeHID::list_devices(&api))
 }
 
 // only create transport without IOTA specific calls
+//
+// waits up to 30 seconds if the device is in use by another transport,
+// `callback` is registered as observer (see [Transport::add_observer])
 pub fn create_transport(
     transport_type: &TransportTypes,
     callback: Option<Callback>,
 ) -> Result<Transport, APIError> {
-    debug!("transport_mutex try lock");
-    let transport_mutex = try_get_lock(Duration::from_secs(30))?;
-    debug!("transport_mutex locked");
+    create_transport_with_timeout(transport_type, callback, TRANSPORT_LOCK_TIMEOUT)
+}
+
+/// Like [create_transport] but waits at most `timeout` for the device to become available
+///
+/// Each device (HID path) or simulator host has its own lock, waiting callers are served
+/// in order of arrival. Returns `APIError::Timeout` if the lock couldn't be acquired in time.
+pub fn create_transport_with_timeout(
+    transport_type: &TransportTypes,
+    callback: Option<Callback>,
+    timeout: Duration,
+) -> Result<Transport, APIError> {
+    open_transport(transport_type, callback, |key| lock::lock(key, timeout))
+}
+
+/// Like [create_transport] but returns `APIError::TransportBusy` instead of waiting
+pub fn try_create_transport(
+    transport_type: &TransportTypes,
+    callback: Option<Callback>,
+) -> Result<Transport, APIError> {
+    open_transport(transport_type, callback, lock::try_lock)
+}
+
+// simulators are told apart by host and port unless they are named explicitly
+fn speculos_lock_key(instance: Option<&str>, host: &str, port: u16) -> String {
+    match instance {
+        Some(instance) => format!("speculos-instance://{}", instance),
+        None => format!("speculos://{}:{}", host, port),
+    }
+}
+
+fn open_transport<F>(
+    transport_type: &TransportTypes,
+    callback: Option<Callback>,
+    lock: F,
+) -> Result<Transport, APIError>
+where
+    F: FnOnce(&str) -> Result<DeviceLockGuard, APIError>,
+{
+    let observers: Vec<Arc<dyn Observer>> = match callback {
+        Some(callback) => vec![Arc::new(CallbackObserver(callback))],
+        None => Vec::new(),
+    };
+
     let transport = match transport_type {
-        TransportTypes::TCP => Transport {
-            _transport_mutex: transport_mutex,
-            transport: LedgerTransport::TCP(TransportTCP::new("127.0.0.1", 9999, callback)),
+        TransportTypes::TCP(config) => Transport {
+            _lock: lock(&config.lock_key())?,
+            transport: LedgerTransport::TCP(TransportTCP::from_config(config)),
+            transport_type: transport_type.clone(),
+            observers,
+            retry_policy: Mutex::new(None),
+        },
+        #[cfg(feature = "speculos-http")]
+        TransportTypes::HTTP(config) => Transport {
+            _lock: lock(&config.lock_key())?,
+            transport: LedgerTransport::HTTP(TransportHTTP::from_config(config)),
+            transport_type: transport_type.clone(),
+            observers,
+            retry_policy: Mutex::new(None),
         },
-        TransportTypes::NativeHID => {
-            let api = hidapi::HidApi::new().map_err(|_| APIError::TransportError)?;
+        TransportTypes::NativeHID(selector) => {
+            let api = hidapi::HidApi::new().map_err(|e| APIError::HID(e.into()))?;
+            let device = TransportNativeHID::find_device(&api, selector).map_err(APIError::HID)?;
+            // lock before opening, opening the same device twice locks it up
+            let device_lock = lock(&format!("hid://{}", device.path().to_string_lossy()))?;
             Transport {
-                _transport_mutex: transport_mutex,
+                _lock: device_lock,
                 transport: LedgerTransport::NativeHID(
-                    TransportNativeHID::new(&api).map_err(|_| APIError::TransportError)?,
+                    TransportNativeHID::open_device(&api, device).map_err(APIError::HID)?,
                 ),
+                transport_type: transport_type.clone(),
+                observers,
+                retry_policy: Mutex::new(None),
             }
         }
+        TransportTypes::Mock(mock) => Transport {
+            _lock: lock(&mock.lock_key())?,
+            transport: LedgerTransport::Mock(mock.clone()),
+            transport_type: transport_type.clone(),
+            observers,
+            retry_policy: Mutex::new(None),
+        },
     };
     Ok(transport)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serial_test::serial;
+
+    // sets (or removes) the simulator variables, the previous values are not restored
+    fn set_env(host: Option<&str>, port: Option<&str>, api_port: Option<&str>) {
+        for (name, value) in [
+            (SIMULATOR_HOST_ENV, host),
+            (SIMULATOR_PORT_ENV, port),
+            ("LEDGER_SIMULATOR_API_PORT", api_port),
+        ] {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn tcp_from_env() {
+        set_env(None, None, None);
+        assert_eq!(TCPConfig::from_env(), TCPConfig::default());
+        assert_eq!(TCPConfig::default(), TCPConfig::new("127.0.0.1", 9999));
+
+        set_env(Some("speculos"), Some("40000"), Some("40001"));
+        let config = TCPConfig::from_env();
+        assert_eq!(config, TCPConfig::new("speculos", 40000));
+        assert_eq!(
+            config.connect_timeout,
+            Some(SIMULATOR_DEFAULT_CONNECT_TIMEOUT)
+        );
+
+        // invalid ports fall back to the default (with a warning)
+        for port in ["", "x", "65536", "-1"] {
+            set_env(Some("speculos"), Some(port), None);
+            assert_eq!(TCPConfig::from_env(), TCPConfig::new("speculos", 9999));
+        }
+        set_env(None, None, None);
+    }
+
+    #[cfg(feature = "speculos-http")]
+    #[test]
+    #[serial]
+    fn http_from_env() {
+        set_env(None, None, None);
+        assert_eq!(HTTPConfig::from_env(), HTTPConfig::new("127.0.0.1", 5000));
+
+        // the APDU port doesn't change the API port
+        set_env(Some("speculos"), Some("40000"), Some("40001"));
+        assert_eq!(HTTPConfig::from_env(), HTTPConfig::new("speculos", 40001));
+
+        set_env(Some("speculos"), Some("40000"), Some("api"));
+        assert_eq!(HTTPConfig::from_env(), HTTPConfig::new("speculos", 5000));
+        set_env(None, None, None);
+    }
+
+    #[test]
+    fn tcp_builder() {
+        let config = TCPConfig::default()
+            .with_host("speculos")
+            .with_port(40000)
+            .with_connect_timeout(None)
+            .with_read_timeout(Some(Duration::from_secs(1)))
+            .with_write_timeout(Some(Duration::from_secs(2)))
+            .with_instance("ci");
+        assert_eq!(
+            config,
+            TCPConfig {
+                host: String::from("speculos"),
+                port: 40000,
+                connect_timeout: None,
+                read_timeout: Some(Duration::from_secs(1)),
+                write_timeout: Some(Duration::from_secs(2)),
+                instance: Some(String::from("ci")),
+            }
+        );
+    }
+
+    #[cfg(feature = "speculos-http")]
+    #[test]
+    fn http_builder() {
+        let config = HTTPConfig::default()
+            .with_host("speculos")
+            .with_port(40001)
+            .with_connect_timeout(None)
+            .with_read_timeout(Some(Duration::from_secs(1)))
+            .with_instance("ci");
+        assert_eq!(
+            config,
+            HTTPConfig {
+                host: String::from("speculos"),
+                port: 40001,
+                connect_timeout: None,
+                read_timeout: Some(Duration::from_secs(1)),
+                instance: Some(String::from("ci")),
+            }
+        );
+        assert_eq!(config.base_url(), "http://speculos:40001");
+    }
+
+    #[cfg(feature = "speculos-http")]
+    #[test]
+    fn speculos_lock_shared_by_tcp_and_http() {
+        let tcp = TransportTypes::TCP(
+            TCPConfig::new("speculos-lock-test", 9999).with_instance("speculos-lock-test"),
+        );
+        let http = TransportTypes::HTTP(
+            HTTPConfig::new("speculos-lock-test", 5000).with_instance("speculos-lock-test"),
+        );
+
+        let transport = try_create_transport(&tcp, None).unwrap();
+        assert!(matches!(
+            try_create_transport(&http, None),
+            Err(APIError::TransportBusy)
+        ));
+        drop(transport);
+        assert!(try_create_transport(&http, None).is_ok());
+    }
+
+    #[cfg(feature = "speculos-http")]
+    #[test]
+    fn speculos_lock_per_port() {
+        let first = TransportTypes::TCP(TCPConfig::new("speculos-port-test", 9999));
+        let second = TransportTypes::TCP(TCPConfig::new("speculos-port-test", 9998));
+        let api = TransportTypes::HTTP(HTTPConfig::new("speculos-port-test", 5000));
+
+        let _first = try_create_transport(&first, None).unwrap();
+        let _second = try_create_transport(&second, None).unwrap();
+        let _api = try_create_transport(&api, None).unwrap();
+        assert!(matches!(
+            try_create_transport(&first, None),
+            Err(APIError::TransportBusy)
+        ));
+    }
+}
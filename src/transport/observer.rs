@@ -0,0 +1,134 @@
+//! Hooks to watch the APDU traffic of a [Transport](crate::transport::Transport)
+//!
+//! Observers are called for every exchange, independent of the kind of transport.
+
+use std::error::Error;
+
+use crate::ledger::ledger_transport::{APDUAnswer, APDUCommand};
+
+/// Called with every answered command (legacy hook, see [Observer])
+pub type Callback = fn(apdu_command: &APDUCommand<Vec<u8>>, apdu_answer: &APDUAnswer<Vec<u8>>);
+
+/// Exchange event as seen by closure observers
+pub enum TransportEvent<'a> {
+    /// command is about to be sent
+    Request(&'a APDUCommand<Vec<u8>>),
+    /// answer was received (including answers with an error status word)
+    Answer(&'a APDUCommand<Vec<u8>>, &'a APDUAnswer<Vec<u8>>),
+    /// command couldn't be exchanged
+    Error(&'a APDUCommand<Vec<u8>>, &'a (dyn Error + 'static)),
+}
+
+/// Observer of the exchanges of a transport
+///
+/// Implemented for closures taking a [TransportEvent]. Observers are shared between threads,
+/// state has to be kept behind a `Mutex` or atomics.
+pub trait Observer: Send + Sync {
+    fn on_request(&self, _command: &APDUCommand<Vec<u8>>) {}
+
+    fn on_answer(&self, _command: &APDUCommand<Vec<u8>>, _answer: &APDUAnswer<Vec<u8>>) {}
+
+    fn on_error(&self, _command: &APDUCommand<Vec<u8>>, _error: &(dyn Error + 'static)) {}
+}
+
+impl<F> Observer for F
+where
+    F: Fn(&TransportEvent<'_>) + Send + Sync,
+{
+    fn on_request(&self, command: &APDUCommand<Vec<u8>>) {
+        self(&TransportEvent::Request(command))
+    }
+
+    fn on_answer(&self, command: &APDUCommand<Vec<u8>>, answer: &APDUAnswer<Vec<u8>>) {
+        self(&TransportEvent::Answer(command, answer))
+    }
+
+    fn on_error(&self, command: &APDUCommand<Vec<u8>>, error: &(dyn Error + 'static)) {
+        self(&TransportEvent::Error(command, error))
+    }
+}
+
+// adapter for the `callback` parameter of `create_transport`
+pub(crate) struct CallbackObserver(pub(crate) Callback);
+
+impl Observer for CallbackObserver {
+    fn on_answer(&self, command: &APDUCommand<Vec<u8>>, answer: &APDUAnswer<Vec<u8>>) {
+        (self.0)(command, answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use crate::api;
+    use crate::transport::errors::LedgerMockError;
+    use crate::transport::{create_transport, Exchange, TransportMock, TransportTypes};
+    use crate::APIError;
+
+    static CALLBACK_ANSWERS: AtomicUsize = AtomicUsize::new(0);
+
+    fn callback(_command: &APDUCommand<Vec<u8>>, _answer: &APDUAnswer<Vec<u8>>) {
+        CALLBACK_ANSWERS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn events() {
+        let mock = TransportMock::new();
+        mock.expect_ok(api::reset::command(), &[])
+            .expect_retcode(api::user_confirm::command(), 0x6985)
+            .expect_with(|_| Err(LedgerMockError::Comm(String::from("unplugged"))));
+
+        let mut transport =
+            create_transport(&TransportTypes::Mock(mock.clone()), Some(callback)).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        transport.add_observer(Arc::new({
+            let events = events.clone();
+            move |event: &TransportEvent<'_>| {
+                let event = match event {
+                    TransportEvent::Request(command) => format!("request {:02x}", command.ins),
+                    TransportEvent::Answer(command, answer) => {
+                        format!("answer {:02x} {:04x}", command.ins, answer.retcode())
+                    }
+                    TransportEvent::Error(command, error) => {
+                        format!("error {:02x} {}", command.ins, error)
+                    }
+                };
+                events.lock().unwrap().push(event);
+            }
+        }));
+
+        transport.exchange(&api::reset::command()).unwrap();
+        // an error status word is still an answer
+        transport.exchange(&api::user_confirm::command()).unwrap();
+        assert!(matches!(
+            transport.exchange(&api::sign::command(0)),
+            Err(APIError::Mock(LedgerMockError::Comm(_)))
+        ));
+
+        let reset = api::reset::command().ins;
+        let user_confirm = api::user_confirm::command().ins;
+        let sign = api::sign::command(0).ins;
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                format!("request {:02x}", reset),
+                format!("answer {:02x} 9000", reset),
+                format!("request {:02x}", user_confirm),
+                format!("answer {:02x} 6985", user_confirm),
+                format!("request {:02x}", sign),
+                format!(
+                    "error {:02x} {}",
+                    sign,
+                    LedgerMockError::Comm(String::from("unplugged"))
+                ),
+            ]
+        );
+        // the legacy callback only sees answers
+        assert_eq!(CALLBACK_ANSWERS.load(Ordering::SeqCst), 2);
+        mock.verify().unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use crate::api::constants::{APDUInstructions, APDUInstructionsBolos, APDUCLASS, APDUCLASSB0};
+use crate::api::errors::APIError;
+use crate::ledger::ledger_transport::APDUCommand;
+#[cfg(feature = "speculos-http")]
+use crate::transport::errors::LedgerHTTPError;
+use crate::transport::errors::{LedgerHIDError, LedgerMockError, LedgerTCPError};
+
+use std::ops::Deref;
+use std::time::Duration;
+
+/// Classes of transport errors a [RetryPolicy] can retry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryClass {
+    /// endpoint not reachable (e.g. simulator restarting)
+    Connect,
+    /// connection dropped during the exchange
+    ConnectionLost,
+    /// no answer within the read timeout
+    Timeout,
+    /// broken HID communication (hidapi errors, framing), not an unplugged device
+    Comm,
+}
+
+impl RetryClass {
+    /// Class of a transport error, `None` for status words and errors retrying can't fix
+    pub fn of(e: &APIError) -> Option<RetryClass> {
+        match e {
+            APIError::Timeout => Some(RetryClass::Timeout),
+            APIError::TCP(LedgerTCPError::ConnectError(_))
+            | APIError::TCP(LedgerTCPError::ConnectionRefused) => Some(RetryClass::Connect),
+            #[cfg(feature = "speculos-http")]
+            APIError::HTTP(LedgerHTTPError::ConnectError(_)) => Some(RetryClass::Connect),
+            APIError::TCP(LedgerTCPError::ConnectionLost)
+            | APIError::TCP(LedgerTCPError::InnerError(_)) => Some(RetryClass::ConnectionLost),
+            APIError::HID(LedgerHIDError::Comm(_))
+            | APIError::HID(LedgerHIDError::Hid(_))
+            | APIError::HID(LedgerHIDError::Framing(_))
+            | APIError::Mock(LedgerMockError::Comm(_)) => Some(RetryClass::Comm),
+            _ => None,
+        }
+    }
+}
+
+/// Retry policy of a [Transport](crate::transport::Transport)
+///
+/// Only commands that can be repeated without side effects are retried (see
+/// [RetryPolicy::is_repeatable]), `user_confirm` or `sign` aren't retried by the policy.
+/// The upload of the data buffer is restarted from `clear_data_buffer` instead, its commands
+/// are sent once per try.
+///
+/// Independent of the policy, the TCP transport reconnects once if the connection was
+/// closed in the meantime. It only sends a command again if it couldn't be written or is
+/// repeatable, so a command the device may have executed already isn't sent twice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// number of tries including the first one
+    pub attempts: u32,
+    /// wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+    /// upper bound of the wait between two tries
+    pub max_backoff: Duration,
+    /// error classes that are retried
+    pub retry_on: Vec<RetryClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            retry_on: vec![
+                RetryClass::Connect,
+                RetryClass::ConnectionLost,
+                RetryClass::Comm,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    pub fn none() -> Self {
+        Self {
+            attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_retry_on(mut self, retry_on: &[RetryClass]) -> Self {
+        self.retry_on = retry_on.to_vec();
+        self
+    }
+
+    /// Error `e` of try number `attempt` (starting at 1) is worth another try
+    pub fn should_retry(&self, e: &APIError, attempt: u32) -> bool {
+        attempt < self.attempts
+            && matches!(RetryClass::of(e), Some(class) if self.retry_on.contains(&class))
+    }
+
+    /// Wait before the try after `attempt`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Command can be sent again without changing the outcome
+    ///
+    /// Commands that need user interaction or change the signing state of the app
+    /// (`prepare_signing`, `user_confirm`, `sign`, opening apps, ...) and single blocks of
+    /// the data buffer upload are not repeatable.
+    pub fn is_repeatable<I: Deref<Target = [u8]>>(command: &APDUCommand<I>) -> bool {
+        const GET_APP_CONFIG: u8 = APDUInstructions::GetAppConfig as u8;
+        const SET_ACCOUNT: u8 = APDUInstructions::SetAccount as u8;
+        const GET_DATA_BUFFER_STATE: u8 = APDUInstructions::GetDataBufferState as u8;
+        const READ_DATA_BLOCK: u8 = APDUInstructions::ReadDataBlock as u8;
+        const CLEAR_DATA_BUFFER: u8 = APDUInstructions::ClearDataBuffer as u8;
+        const GENERATE_ADDRESSES: u8 = APDUInstructions::GenerateAddresses as u8;
+        const GENERATE_PUBLIC_KEYS: u8 = APDUInstructions::GeneratePublicKeys as u8;
+        const DUMP_MEMORY: u8 = APDUInstructions::DumpMemory as u8;
+        const RESET: u8 = APDUInstructions::Reset as u8;
+        const GET_APP_VERSION: u8 = APDUInstructionsBolos::GetAppVersionB0 as u8;
+
+        match (command.cla, command.ins) {
+            (
+                APDUCLASS,
+                GET_APP_CONFIG
+                | SET_ACCOUNT
+                | GET_DATA_BUFFER_STATE
+                | READ_DATA_BLOCK
+                | CLEAR_DATA_BUFFER
+                | DUMP_MEMORY
+                | RESET,
+            ) => true,
+            // only if the result isn't shown on the device
+            (APDUCLASS, GENERATE_ADDRESSES | GENERATE_PUBLIC_KEYS) => command.p1 == 0,
+            (APDUCLASSB0, GET_APP_VERSION) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api;
+    use crate::transport::{create_transport, Transport, TransportMock, TransportTypes};
+
+    fn transport(mock: &TransportMock) -> Transport {
+        let transport = create_transport(&TransportTypes::Mock(mock.clone()), None).unwrap();
+        transport.set_retry_policy(Some(
+            RetryPolicy::default().with_backoff(Duration::ZERO, Duration::ZERO),
+        ));
+        transport
+    }
+
+    fn fail(mock: &TransportMock) {
+        mock.expect_with(|_| Err(LedgerMockError::Comm("broken pipe".to_string())));
+    }
+
+    #[test]
+    fn classes() {
+        let hid = |e| RetryClass::of(&APIError::HID(e));
+        assert_eq!(
+            hid(LedgerHIDError::Hid(hidapi::HidError::HidApiErrorEmpty)),
+            Some(RetryClass::Comm)
+        );
+        assert_eq!(
+            hid(LedgerHIDError::Disconnected(
+                hidapi::HidError::HidApiErrorEmpty
+            )),
+            None
+        );
+        assert_eq!(
+            RetryClass::of(&APIError::Timeout),
+            Some(RetryClass::Timeout)
+        );
+        assert_eq!(
+            RetryClass::of(&APIError::TCP(LedgerTCPError::ConnectionRefused)),
+            Some(RetryClass::Connect)
+        );
+        assert_eq!(RetryClass::of(&APIError::ConditionsOfUseNotSatisfied), None);
+    }
+
+    #[test]
+    fn retry_after_failure() {
+        let mock = TransportMock::new();
+        let transport = transport(&mock);
+        fail(&mock);
+        mock.expect_ok(api::get_app_config::command(), &[1, 0, 0, 0, 0, 0]);
+
+        api::get_app_config::exec(&transport).unwrap();
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn no_retry_of_user_confirm() {
+        let mock = TransportMock::new();
+        let transport = transport(&mock);
+        fail(&mock);
+
+        assert!(matches!(
+            api::user_confirm::exec(&transport),
+            Err(APIError::Mock(LedgerMockError::Comm(_)))
+        ));
+        mock.verify().unwrap();
+    }
+
+    #[test]
+    fn upload_retried_as_a_whole() {
+        let mock = TransportMock::new();
+        let app_config = [1, 0, 0, 0, 0, 0];
+        mock.expect_ok(api::reset::command(), &[])
+            .expect_ok(api::get_app_config::command(), &app_config)
+            .expect_ok(api::get_data_buffer_state::command(), &[0, 0, 0, 251, 8])
+            .expect_ok(api::get_app_config::command(), &app_config)
+            .expect_ok(
+                api::set_account::command(api::constants::AppModes::ModeIOTAStardust, 0x80000000),
+                &[],
+            );
+        let ledger = crate::get_ledger_by_transport(0x107a, 0x80000000, transport(&mock)).unwrap();
+
+        // each try of the upload sends `clear_data_buffer` once, 3 tries in total
+        for _ in 0..3 {
+            fail(&mock);
+        }
+        assert!(matches!(
+            ledger.prepare_blind_signing(vec![Default::default()], vec![0; 32]),
+            Err(APIError::Mock(LedgerMockError::Comm(_)))
+        ));
+        mock.verify().unwrap();
+    }
+}
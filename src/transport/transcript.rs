@@ -0,0 +1,293 @@
+//! APDU transcripts as recorded by the `watcher_cb` of the `cli`/`cli_stardust` examples
+//!
+//! A transcript can be replayed with [TransportMock::from_transcript], each command has to match
+//! the recorded one and is answered with the recorded answer.
+
+use std::path::Path;
+
+use crate::ledger::ledger_apdu::{APDUAnswer, APDUCommand};
+use crate::transport::errors::LedgerMockError;
+use crate::transport::TransportMock;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    /// one json object per line, command and answer alternating (feature `transcript-json`)
+    #[cfg(feature = "transcript-json")]
+    Json,
+    /// `>>` command and `<<` answer lines (raw bytes hex encoded)
+    Hex,
+    /// 32bit LE length followed by the raw bytes, command and answer alternating
+    Bin,
+}
+
+/// recorded command and the answer it got
+pub type RecordedExchange = (APDUCommand<Vec<u8>>, APDUAnswer<Vec<u8>>);
+
+pub struct Transcript {
+    pub exchanges: Vec<RecordedExchange>,
+}
+
+fn invalid(msg: &str) -> LedgerMockError {
+    LedgerMockError::InvalidTranscript(String::from(msg))
+}
+
+fn command_from_raw(raw: &[u8]) -> Result<APDUCommand<Vec<u8>>, LedgerMockError> {
+    APDUCommand::from_raw(raw).map_err(|_| invalid("invalid command length"))
+}
+
+fn answer_from_raw(raw: Vec<u8>) -> Result<APDUAnswer<Vec<u8>>, LedgerMockError> {
+    APDUAnswer::from_answer(raw).map_err(|_| invalid("answer too short"))
+}
+
+#[cfg(feature = "transcript-json")]
+fn json_bytes(value: &serde_json::Value) -> Result<Vec<u8>, LedgerMockError> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid("data is not an array"))?
+        .iter()
+        .map(|b| {
+            b.as_u64()
+                .and_then(|b| u8::try_from(b).ok())
+                .ok_or_else(|| invalid("data is not a byte array"))
+        })
+        .collect()
+}
+
+#[cfg(feature = "transcript-json")]
+fn json_u8(value: &serde_json::Value) -> Result<u8, LedgerMockError> {
+    value
+        .as_u64()
+        .and_then(|v| u8::try_from(v).ok())
+        .ok_or_else(|| invalid("invalid command header"))
+}
+
+impl Transcript {
+    pub fn parse(bytes: &[u8], format: TranscriptFormat) -> Result<Self, LedgerMockError> {
+        let exchanges = match format {
+            #[cfg(feature = "transcript-json")]
+            TranscriptFormat::Json => Self::parse_json(bytes)?,
+            TranscriptFormat::Hex => Self::parse_hex(bytes)?,
+            TranscriptFormat::Bin => Self::parse_bin(bytes)?,
+        };
+        Ok(Self { exchanges })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        format: TranscriptFormat,
+    ) -> Result<Self, LedgerMockError> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| LedgerMockError::InvalidTranscript(format!("can't read file: {}", e)))?;
+        Self::parse(&bytes, format)
+    }
+
+    fn lines(bytes: &[u8]) -> Result<Vec<&str>, LedgerMockError> {
+        Ok(std::str::from_utf8(bytes)
+            .map_err(|_| invalid("not utf8"))?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+
+    #[cfg(feature = "transcript-json")]
+    fn parse_json(bytes: &[u8]) -> Result<Vec<RecordedExchange>, LedgerMockError> {
+        let lines = Self::lines(bytes)?;
+        if lines.len() % 2 != 0 {
+            return Err(invalid("command without answer"));
+        }
+
+        lines
+            .chunks(2)
+            .map(|pair| {
+                let command: serde_json::Value =
+                    serde_json::from_str(pair[0]).map_err(|_| invalid("invalid json"))?;
+                let answer: serde_json::Value =
+                    serde_json::from_str(pair[1]).map_err(|_| invalid("invalid json"))?;
+
+                let command = APDUCommand {
+                    cla: json_u8(&command["cla"])?,
+                    ins: json_u8(&command["ins"])?,
+                    p1: json_u8(&command["p1"])?,
+                    p2: json_u8(&command["p2"])?,
+                    data: json_bytes(&command["data"])?,
+                };
+
+                let retcode = answer["retcode"]
+                    .as_u64()
+                    .and_then(|r| u16::try_from(r).ok())
+                    .ok_or_else(|| invalid("invalid retcode"))?;
+                let mut raw_answer = json_bytes(&answer["data"])?;
+                raw_answer.extend_from_slice(&retcode.to_be_bytes());
+
+                Ok((command, answer_from_raw(raw_answer)?))
+            })
+            .collect()
+    }
+
+    fn parse_hex(bytes: &[u8]) -> Result<Vec<RecordedExchange>, LedgerMockError> {
+        let lines = Self::lines(bytes)?;
+        if lines.len() % 2 != 0 {
+            return Err(invalid("command without answer"));
+        }
+
+        lines
+            .chunks(2)
+            .map(|pair| {
+                let command = pair[0]
+                    .strip_prefix(">>")
+                    .ok_or_else(|| invalid("expected command line (>>)"))?;
+                let answer = pair[1]
+                    .strip_prefix("<<")
+                    .ok_or_else(|| invalid("expected answer line (<<)"))?;
+
+                let command = hex::decode(command).map_err(|_| invalid("invalid hex"))?;
+                let answer = hex::decode(answer).map_err(|_| invalid("invalid hex"))?;
+                Ok((command_from_raw(&command)?, answer_from_raw(answer)?))
+            })
+            .collect()
+    }
+
+    fn parse_bin(mut bytes: &[u8]) -> Result<Vec<RecordedExchange>, LedgerMockError> {
+        fn next<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], LedgerMockError> {
+            if bytes.len() < 4 {
+                return Err(invalid("truncated length"));
+            }
+            let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+            if bytes.len() < 4 + len {
+                return Err(invalid("truncated data"));
+            }
+            let data = &bytes[4..4 + len];
+            *bytes = &bytes[4 + len..];
+            Ok(data)
+        }
+
+        let mut exchanges = Vec::new();
+        while !bytes.is_empty() {
+            let command = command_from_raw(next(&mut bytes)?)?;
+            let answer = answer_from_raw(next(&mut bytes)?.to_vec())?;
+            exchanges.push((command, answer));
+        }
+        Ok(exchanges)
+    }
+}
+
+impl TransportMock {
+    /// Mock that expects exactly the commands of the transcript in the recorded order
+    pub fn from_transcript(transcript: Transcript) -> Self {
+        let mock = Self::new();
+        for (command, answer) in transcript.exchanges {
+            mock.expect(command, answer);
+        }
+        mock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::api;
+
+    // `reset` and `get_app_config` of the IOTA app 1.0.2
+    fn check(transcript: Transcript) {
+        let expected: Vec<(APDUCommand<Vec<u8>>, Vec<u8>)> = vec![
+            (api::reset::command(), vec![0x90, 0x00]),
+            (
+                api::get_app_config::command(),
+                vec![1, 0, 2, 0, 0, 0, 0x90, 0x00],
+            ),
+        ];
+        assert_eq!(transcript.exchanges.len(), expected.len());
+        for ((command, answer), (expected_command, expected_answer)) in
+            transcript.exchanges.iter().zip(&expected)
+        {
+            assert_eq!(command.serialize(), expected_command.serialize());
+            assert_eq!(answer.data(), &expected_answer[..expected_answer.len() - 2]);
+            assert_eq!(answer.retcode(), 0x9000);
+        }
+
+        // replays in the recorded order
+        let mock = TransportMock::from_transcript(transcript);
+        api::reset::exec(&mock).unwrap();
+        assert_eq!(
+            api::get_app_config::exec(&mock).unwrap().app_version_patch,
+            2
+        );
+        mock.verify().unwrap();
+    }
+
+    fn is_invalid(res: Result<Transcript, LedgerMockError>) -> bool {
+        matches!(res, Err(LedgerMockError::InvalidTranscript(_)))
+    }
+
+    #[cfg(feature = "transcript-json")]
+    #[test]
+    fn json() {
+        check(
+            Transcript::parse(
+                include_bytes!("testdata/transcript.jsonl"),
+                TranscriptFormat::Json,
+            )
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn hex() {
+        check(
+            Transcript::parse(
+                include_bytes!("testdata/transcript.hex"),
+                TranscriptFormat::Hex,
+            )
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn bin() {
+        check(
+            Transcript::parse(
+                include_bytes!("testdata/transcript.bin"),
+                TranscriptFormat::Bin,
+            )
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let hex = |s: &str| Transcript::parse(s.as_bytes(), TranscriptFormat::Hex);
+        // command without answer
+        assert!(is_invalid(hex(">>7bff000000")));
+        // answer first
+        assert!(is_invalid(hex("<<9000\n>>7bff000000")));
+        assert!(is_invalid(hex(">>7bff00000x\n<<9000")));
+        // Lc doesn't match the data
+        assert!(is_invalid(hex(">>7bff000001\n<<9000")));
+        // no status word
+        assert!(is_invalid(hex(">>7bff000000\n<<90")));
+
+        let bin = include_bytes!("testdata/transcript.bin");
+        assert!(is_invalid(Transcript::parse(
+            &bin[..bin.len() - 1],
+            TranscriptFormat::Bin
+        )));
+        assert!(is_invalid(Transcript::parse(
+            &[5, 0],
+            TranscriptFormat::Bin
+        )));
+
+        #[cfg(feature = "transcript-json")]
+        {
+            let json = |s: &str| Transcript::parse(s.as_bytes(), TranscriptFormat::Json);
+            assert!(is_invalid(json(
+                "{\"cla\": 123}\n{\"data\": [], \"retcode\": 36864}"
+            )));
+            assert!(is_invalid(json(
+                "{\"cla\": 123, \"ins\": 255, \"p1\": 0, \"p2\": 0, \"data\": [256]}\n{\"data\": [], \"retcode\": 36864}"
+            )));
+            assert!(is_invalid(json("not json\n{}")));
+        }
+    }
+}
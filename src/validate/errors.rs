@@ -0,0 +1,54 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LedgerEssenceError {
+    /// essence ends in the middle of a field
+    #[error("Essence: truncated at byte {0}")]
+    Truncated(usize),
+    /// bytes after the end of the essence
+    #[error("Essence: {0} trailing byte(s)")]
+    TrailingBytes(usize),
+    /// only regular transaction essences are signed
+    #[error("Essence: unsupported essence type {0}")]
+    UnsupportedEssenceType(u8),
+    #[error("Essence: {0} inputs (1 to {max} supported)", max = super::INPUTS_MAX_COUNT)]
+    InputCount(usize),
+    #[error("Essence: {0} outputs (1 to {max} supported)", max = super::OUTPUTS_MAX_COUNT)]
+    OutputCount(usize),
+    /// only UTXO inputs are supported
+    #[error("Essence: input {input}: unsupported input type {kind}")]
+    UnsupportedInputType { input: usize, kind: u8 },
+    /// only Basic outputs are supported
+    #[error("Essence: output {output}: unsupported output type {kind}")]
+    UnsupportedOutputType { output: usize, kind: u8 },
+    #[error("Essence: output {0}: native tokens are not supported")]
+    NativeTokensNotSupported(usize),
+    /// outputs need exactly one (address) unlock condition
+    #[error("Essence: output {output}: {count} unlock conditions (exactly 1 supported)")]
+    UnlockConditionCount { output: usize, count: usize },
+    #[error("Essence: output {output}: unsupported unlock condition type {kind}")]
+    UnsupportedUnlockCondition { output: usize, kind: u8 },
+    #[error("Essence: output {output}: unsupported address type {kind}")]
+    UnsupportedAddressType { output: usize, kind: u8 },
+    #[error("Essence: output {0}: features are not supported")]
+    FeaturesNotSupported(usize),
+    /// only tagged data payloads are supported
+    #[error("Essence: unsupported payload type {0}")]
+    UnsupportedPayloadType(u32),
+    /// payload length doesn't match its content
+    #[error("Essence: invalid payload length {0}")]
+    InvalidPayloadLength(u32),
+    /// one key path is needed per input
+    #[error("Essence: {keys} key indices for {inputs} inputs")]
+    KeyIndexCount { inputs: usize, keys: usize },
+    /// index and change of the key path of an input have to be hardened
+    #[error("Essence: key path of input {0} is not hardened")]
+    KeyIndexNotHardened(usize),
+    #[error("Essence: remainder index {index} out of range ({outputs} outputs)")]
+    RemainderIndexOutOfRange { index: u16, outputs: usize },
+    #[error("Essence: remainder key path is not hardened")]
+    RemainderNotHardened,
+    /// essence and key indices don't fit into the data buffer of the device
+    #[error("Essence: {size} bytes don't fit into the data buffer ({buffer_size} bytes)")]
+    TooLarge { size: usize, buffer_size: usize },
+}
@@ -0,0 +1,521 @@
+//! Checks an essence before it's uploaded for signing
+//!
+//! Follows the rules of the parser of the Stardust app, so problems are reported with a
+//! specific error instead of `CommandInvalidData` from the device.
+
+mod errors;
+
+use std::convert::TryInto;
+
+pub use errors::LedgerEssenceError;
+
+use crate::api::constants::HARDENED;
+use crate::{LedgerBIP32Index, LedgerRemainder};
+
+pub const INPUTS_MAX_COUNT: usize = 128;
+pub const OUTPUTS_MAX_COUNT: usize = 128;
+
+const REGULAR_TRANSACTION_ESSENCE_TYPE: u8 = 1;
+const UTXO_INPUT_TYPE: u8 = 0;
+const BASIC_OUTPUT_TYPE: u8 = 3;
+const ADDRESS_UNLOCK_CONDITION_TYPE: u8 = 0;
+const ED25519_ADDRESS_TYPE: u8 = 0;
+const TAGGED_DATA_PAYLOAD_TYPE: u32 = 5;
+
+// size of the key path of an input in the data buffer
+const KEY_INDEX_LENGTH: usize = 8;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LedgerEssenceError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(LedgerEssenceError::Truncated(self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LedgerEssenceError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LedgerEssenceError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, LedgerEssenceError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+fn is_hardened(bip32: &LedgerBIP32Index) -> bool {
+    bip32.bip32_index & HARDENED != 0 && bip32.bip32_change & HARDENED != 0
+}
+
+fn check_output(reader: &mut Reader, output: usize) -> Result<(), LedgerEssenceError> {
+    let kind = reader.u8()?;
+    if kind != BASIC_OUTPUT_TYPE {
+        return Err(LedgerEssenceError::UnsupportedOutputType { output, kind });
+    }
+
+    // amount
+    reader.bytes(8)?;
+
+    if reader.u8()? != 0 {
+        return Err(LedgerEssenceError::NativeTokensNotSupported(output));
+    }
+
+    let count = reader.u8()? as usize;
+    if count != 1 {
+        return Err(LedgerEssenceError::UnlockConditionCount { output, count });
+    }
+    let kind = reader.u8()?;
+    if kind != ADDRESS_UNLOCK_CONDITION_TYPE {
+        return Err(LedgerEssenceError::UnsupportedUnlockCondition { output, kind });
+    }
+    let kind = reader.u8()?;
+    if kind != ED25519_ADDRESS_TYPE {
+        return Err(LedgerEssenceError::UnsupportedAddressType { output, kind });
+    }
+    reader.bytes(32)?;
+
+    if reader.u8()? != 0 {
+        return Err(LedgerEssenceError::FeaturesNotSupported(output));
+    }
+    Ok(())
+}
+
+fn check_payload(reader: &mut Reader) -> Result<(), LedgerEssenceError> {
+    let len = reader.u32()?;
+    if len == 0 {
+        return Ok(());
+    }
+    let start = reader.pos;
+    let kind = reader.u32()?;
+    if kind != TAGGED_DATA_PAYLOAD_TYPE {
+        return Err(LedgerEssenceError::UnsupportedPayloadType(kind));
+    }
+    let tag_len = reader.u8()? as usize;
+    reader.bytes(tag_len)?;
+    let data_len = reader.u32()? as usize;
+    reader.bytes(data_len)?;
+    if reader.pos - start != len as usize {
+        return Err(LedgerEssenceError::InvalidPayloadLength(len));
+    }
+    Ok(())
+}
+
+/// Validates a serialized Stardust essence with the key paths of its inputs
+///
+/// `buffer_size` is the size of the data buffer of the device
+/// (see [LedgerHardwareWallet::get_buffer_size](crate::LedgerHardwareWallet::get_buffer_size)).
+pub fn validate_essence(
+    essence: &[u8],
+    key_indices: &[LedgerBIP32Index],
+    remainder: Option<&LedgerRemainder>,
+    buffer_size: usize,
+) -> Result<(), LedgerEssenceError> {
+    let mut reader = Reader {
+        data: essence,
+        pos: 0,
+    };
+
+    let kind = reader.u8()?;
+    if kind != REGULAR_TRANSACTION_ESSENCE_TYPE {
+        return Err(LedgerEssenceError::UnsupportedEssenceType(kind));
+    }
+
+    // network id
+    reader.bytes(8)?;
+
+    let inputs = reader.u16()? as usize;
+    if inputs == 0 || inputs > INPUTS_MAX_COUNT {
+        return Err(LedgerEssenceError::InputCount(inputs));
+    }
+    for input in 0..inputs {
+        let kind = reader.u8()?;
+        if kind != UTXO_INPUT_TYPE {
+            return Err(LedgerEssenceError::UnsupportedInputType { input, kind });
+        }
+        // transaction id and output index
+        reader.bytes(32 + 2)?;
+    }
+
+    // inputs commitment
+    reader.bytes(32)?;
+
+    let outputs = reader.u16()? as usize;
+    if outputs == 0 || outputs > OUTPUTS_MAX_COUNT {
+        return Err(LedgerEssenceError::OutputCount(outputs));
+    }
+    for output in 0..outputs {
+        check_output(&mut reader, output)?;
+    }
+
+    check_payload(&mut reader)?;
+
+    if reader.pos != essence.len() {
+        return Err(LedgerEssenceError::TrailingBytes(
+            essence.len() - reader.pos,
+        ));
+    }
+
+    if key_indices.len() != inputs {
+        return Err(LedgerEssenceError::KeyIndexCount {
+            inputs,
+            keys: key_indices.len(),
+        });
+    }
+    if let Some(input) = key_indices.iter().position(|k| !is_hardened(k)) {
+        return Err(LedgerEssenceError::KeyIndexNotHardened(input));
+    }
+
+    if let Some(remainder) = remainder {
+        // all outputs are Basic outputs with an Ed25519 address at this point
+        if remainder.output_index as usize >= outputs {
+            return Err(LedgerEssenceError::RemainderIndexOutOfRange {
+                index: remainder.output_index,
+                outputs,
+            });
+        }
+        if !is_hardened(&remainder.bip32) {
+            return Err(LedgerEssenceError::RemainderNotHardened);
+        }
+    }
+
+    let size = essence.len() + key_indices.len() * KEY_INDEX_LENGTH;
+    if size > buffer_size {
+        return Err(LedgerEssenceError::TooLarge { size, buffer_size });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const BUFFER_SIZE: usize = 2048;
+
+    // essence with basic outputs to Ed25519 addresses, also used by the signing tests
+    pub(crate) struct Essence {
+        kind: u8,
+        inputs: Vec<Vec<u8>>,
+        outputs: Vec<Vec<u8>>,
+        payload: Vec<u8>,
+    }
+
+    impl Essence {
+        pub(crate) fn new(inputs: usize, outputs: usize) -> Self {
+            Self {
+                kind: REGULAR_TRANSACTION_ESSENCE_TYPE,
+                inputs: vec![input(); inputs],
+                outputs: vec![output(); outputs],
+                payload: Vec::new(),
+            }
+        }
+
+        pub(crate) fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = vec![self.kind];
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(&(self.inputs.len() as u16).to_le_bytes());
+            self.inputs.iter().for_each(|i| bytes.extend_from_slice(i));
+            bytes.extend_from_slice(&[0; 32]);
+            bytes.extend_from_slice(&(self.outputs.len() as u16).to_le_bytes());
+            self.outputs.iter().for_each(|o| bytes.extend_from_slice(o));
+            bytes.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&self.payload);
+            bytes
+        }
+    }
+
+    fn input() -> Vec<u8> {
+        let mut input = vec![UTXO_INPUT_TYPE];
+        input.extend_from_slice(&[1; 32]);
+        input.extend_from_slice(&0u16.to_le_bytes());
+        input
+    }
+
+    // Basic output with one Ed25519 address unlock condition
+    fn output() -> Vec<u8> {
+        let mut output = vec![BASIC_OUTPUT_TYPE];
+        output.extend_from_slice(&1_000_000u64.to_le_bytes());
+        // native tokens, unlock conditions
+        output.extend_from_slice(&[0, 1, ADDRESS_UNLOCK_CONDITION_TYPE, ED25519_ADDRESS_TYPE]);
+        output.extend_from_slice(&[2; 32]);
+        // features
+        output.push(0);
+        output
+    }
+
+    fn tagged_data(tag: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut payload = TAGGED_DATA_PAYLOAD_TYPE.to_le_bytes().to_vec();
+        payload.push(tag.len() as u8);
+        payload.extend_from_slice(tag);
+        payload.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        payload.extend_from_slice(data);
+        payload
+    }
+
+    fn key() -> LedgerBIP32Index {
+        LedgerBIP32Index {
+            bip32_index: HARDENED,
+            bip32_change: HARDENED,
+        }
+    }
+
+    fn remainder(output_index: u16) -> LedgerRemainder {
+        LedgerRemainder {
+            output_index,
+            bip32: LedgerBIP32Index {
+                bip32_index: HARDENED | 1,
+                bip32_change: HARDENED | 1,
+            },
+        }
+    }
+
+    // validates with one hardened key per input
+    fn validate(essence: &Essence) -> Result<(), LedgerEssenceError> {
+        validate_essence(
+            &essence.to_bytes(),
+            &vec![key(); essence.inputs.len()],
+            None,
+            BUFFER_SIZE,
+        )
+    }
+
+    // replaces the byte at `pos` of the (only) output
+    fn with_output_byte(pos: usize, value: u8) -> Essence {
+        let mut essence = Essence::new(1, 1);
+        essence.outputs[0][pos] = value;
+        essence
+    }
+
+    #[test]
+    fn valid() {
+        let mut essence = Essence::new(2, 2);
+        essence.payload = tagged_data(b"tag", b"data");
+        validate_essence(
+            &essence.to_bytes(),
+            &[key(), key()],
+            Some(&remainder(1)),
+            BUFFER_SIZE,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = Essence::new(1, 1).to_bytes();
+        assert_eq!(
+            validate_essence(&bytes[..bytes.len() - 1], &[key()], None, BUFFER_SIZE),
+            Err(LedgerEssenceError::Truncated(bytes.len() - 4))
+        );
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut bytes = Essence::new(1, 1).to_bytes();
+        bytes.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            validate_essence(&bytes, &[key()], None, BUFFER_SIZE),
+            Err(LedgerEssenceError::TrailingBytes(2))
+        );
+    }
+
+    #[test]
+    fn essence_type() {
+        let mut essence = Essence::new(1, 1);
+        essence.kind = 2;
+        assert_eq!(
+            validate(&essence),
+            Err(LedgerEssenceError::UnsupportedEssenceType(2))
+        );
+    }
+
+    #[test]
+    fn input_count() {
+        assert_eq!(
+            validate(&Essence::new(0, 1)),
+            Err(LedgerEssenceError::InputCount(0))
+        );
+        assert_eq!(
+            validate(&Essence::new(INPUTS_MAX_COUNT + 1, 1)),
+            Err(LedgerEssenceError::InputCount(INPUTS_MAX_COUNT + 1))
+        );
+    }
+
+    #[test]
+    fn output_count() {
+        assert_eq!(
+            validate(&Essence::new(1, 0)),
+            Err(LedgerEssenceError::OutputCount(0))
+        );
+        assert_eq!(
+            validate(&Essence::new(1, OUTPUTS_MAX_COUNT + 1)),
+            Err(LedgerEssenceError::OutputCount(OUTPUTS_MAX_COUNT + 1))
+        );
+    }
+
+    #[test]
+    fn input_type() {
+        let mut essence = Essence::new(2, 1);
+        essence.inputs[1][0] = 1;
+        assert_eq!(
+            validate(&essence),
+            Err(LedgerEssenceError::UnsupportedInputType { input: 1, kind: 1 })
+        );
+    }
+
+    #[test]
+    fn output_type() {
+        assert_eq!(
+            validate(&with_output_byte(0, 4)),
+            Err(LedgerEssenceError::UnsupportedOutputType { output: 0, kind: 4 })
+        );
+    }
+
+    #[test]
+    fn native_tokens() {
+        assert_eq!(
+            validate(&with_output_byte(9, 1)),
+            Err(LedgerEssenceError::NativeTokensNotSupported(0))
+        );
+    }
+
+    #[test]
+    fn unlock_condition_count() {
+        assert_eq!(
+            validate(&with_output_byte(10, 2)),
+            Err(LedgerEssenceError::UnlockConditionCount {
+                output: 0,
+                count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn unlock_condition_type() {
+        assert_eq!(
+            validate(&with_output_byte(11, 2)),
+            Err(LedgerEssenceError::UnsupportedUnlockCondition { output: 0, kind: 2 })
+        );
+    }
+
+    #[test]
+    fn address_type() {
+        assert_eq!(
+            validate(&with_output_byte(12, 8)),
+            Err(LedgerEssenceError::UnsupportedAddressType { output: 0, kind: 8 })
+        );
+    }
+
+    #[test]
+    fn features() {
+        assert_eq!(
+            validate(&with_output_byte(45, 1)),
+            Err(LedgerEssenceError::FeaturesNotSupported(0))
+        );
+    }
+
+    #[test]
+    fn payload_type() {
+        let mut essence = Essence::new(1, 1);
+        essence.payload = tagged_data(b"tag", b"data");
+        essence.payload[0] = 6;
+        assert_eq!(
+            validate(&essence),
+            Err(LedgerEssenceError::UnsupportedPayloadType(6))
+        );
+    }
+
+    #[test]
+    fn payload_length() {
+        let mut essence = Essence::new(1, 1);
+        essence.payload = tagged_data(b"tag", b"data");
+        let mut bytes = essence.to_bytes();
+        // announce one byte less than the payload has, the rest is trailing
+        let len_pos = bytes.len() - essence.payload.len() - 4;
+        let len = essence.payload.len() as u32 - 1;
+        bytes[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(
+            validate_essence(&bytes, &[key()], None, BUFFER_SIZE),
+            Err(LedgerEssenceError::InvalidPayloadLength(len))
+        );
+    }
+
+    #[test]
+    fn key_index_count() {
+        assert_eq!(
+            validate_essence(&Essence::new(2, 1).to_bytes(), &[key()], None, BUFFER_SIZE),
+            Err(LedgerEssenceError::KeyIndexCount { inputs: 2, keys: 1 })
+        );
+    }
+
+    #[test]
+    fn key_index_not_hardened() {
+        let soft = LedgerBIP32Index {
+            bip32_index: 1,
+            bip32_change: HARDENED,
+        };
+        assert_eq!(
+            validate_essence(
+                &Essence::new(2, 1).to_bytes(),
+                &[key(), soft],
+                None,
+                BUFFER_SIZE
+            ),
+            Err(LedgerEssenceError::KeyIndexNotHardened(1))
+        );
+    }
+
+    #[test]
+    fn remainder_index() {
+        assert_eq!(
+            validate_essence(
+                &Essence::new(1, 2).to_bytes(),
+                &[key()],
+                Some(&remainder(2)),
+                BUFFER_SIZE
+            ),
+            Err(LedgerEssenceError::RemainderIndexOutOfRange {
+                index: 2,
+                outputs: 2
+            })
+        );
+    }
+
+    #[test]
+    fn remainder_not_hardened() {
+        let mut remainder = remainder(0);
+        remainder.bip32.bip32_change = 1;
+        assert_eq!(
+            validate_essence(
+                &Essence::new(1, 1).to_bytes(),
+                &[key()],
+                Some(&remainder),
+                BUFFER_SIZE
+            ),
+            Err(LedgerEssenceError::RemainderNotHardened)
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let bytes = Essence::new(1, 1).to_bytes();
+        let size = bytes.len() + KEY_INDEX_LENGTH;
+        assert_eq!(
+            validate_essence(&bytes, &[key()], None, size - 1),
+            Err(LedgerEssenceError::TooLarge {
+                size,
+                buffer_size: size - 1
+            })
+        );
+    }
+}
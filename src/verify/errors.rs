@@ -0,0 +1,20 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LedgerVerifyError {
+    /// number of unlocks doesn't match the number of inputs
+    #[error("Verify: {unlocks} unlock(s) for {inputs} input(s)")]
+    CountMismatch { inputs: usize, unlocks: usize },
+    /// public key is not a valid Ed25519 point
+    #[error("Verify: input {0}: invalid public key")]
+    InvalidPublicKey(usize),
+    /// signature doesn't match the essence hash and public key
+    #[error("Verify: input {0}: invalid signature")]
+    InvalidSignature(usize),
+    /// public key doesn't belong to the address of the input
+    #[error("Verify: input {0}: public key doesn't match the input address")]
+    AddressMismatch(usize),
+    /// reference to an unlock that isn't an earlier signature unlock of the same address
+    #[error("Verify: input {input}: invalid reference to unlock {index}")]
+    InvalidReference { input: usize, index: u16 },
+}
@@ -0,0 +1,171 @@
+//! Local verification of the unlocks returned by the device (feature `verify`)
+//!
+//! Catches signatures corrupted or replaced between the device and the caller before the
+//! transaction is sent to a node.
+
+mod errors;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+pub use crate::api::constants::ESSENCE_HASH_SIZE_BYTES;
+pub use crate::hash::{address_from_public_key, essence_hash};
+pub use errors::LedgerVerifyError;
+
+use crate::api::constants::ADDRESS_SIZE_BYTES;
+use crate::Unlock;
+
+/// Verifies the unlocks returned by `sign` (in input order)
+///
+/// `input_addresses` are the Ed25519 addresses of the inputs. Every signature has to be valid
+/// for the essence hash and its public key has to hash to the address of the input. References
+/// have to point to an earlier signature unlock of the same address.
+pub fn verify_unlocks(
+    essence: &[u8],
+    input_addresses: &[[u8; ADDRESS_SIZE_BYTES]],
+    unlocks: &[Unlock],
+) -> Result<(), LedgerVerifyError> {
+    if input_addresses.len() != unlocks.len() {
+        return Err(LedgerVerifyError::CountMismatch {
+            inputs: input_addresses.len(),
+            unlocks: unlocks.len(),
+        });
+    }
+
+    let hash = essence_hash(essence);
+    for (input, (address, unlock)) in input_addresses.iter().zip(unlocks).enumerate() {
+        match unlock {
+            Unlock::Signature {
+                public_key,
+                signature,
+            } => {
+                if address_from_public_key(public_key) != *address {
+                    return Err(LedgerVerifyError::AddressMismatch(input));
+                }
+                let key = VerifyingKey::from_bytes(public_key)
+                    .map_err(|_| LedgerVerifyError::InvalidPublicKey(input))?;
+                key.verify(&hash, &Signature::from_bytes(signature))
+                    .map_err(|_| LedgerVerifyError::InvalidSignature(input))?;
+            }
+            Unlock::Reference { index } => {
+                let referenced = *index as usize;
+                let valid = referenced < input
+                    && matches!(unlocks[referenced], Unlock::Signature { .. })
+                    && input_addresses[referenced] == *address;
+                if !valid {
+                    return Err(LedgerVerifyError::InvalidReference {
+                        input,
+                        index: *index,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const ESSENCE: &[u8] = b"essence bytes";
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn address(key: &SigningKey) -> [u8; ADDRESS_SIZE_BYTES] {
+        address_from_public_key(&key.verifying_key().to_bytes())
+    }
+
+    fn unlock(key: &SigningKey, essence: &[u8]) -> Unlock {
+        Unlock::Signature {
+            public_key: key.verifying_key().to_bytes(),
+            signature: key.sign(&essence_hash(essence)).to_bytes(),
+        }
+    }
+
+    #[test]
+    fn valid_signatures() {
+        let (a, b) = (key(1), key(2));
+        verify_unlocks(
+            ESSENCE,
+            &[address(&a), address(&b), address(&a)],
+            &[
+                unlock(&a, ESSENCE),
+                unlock(&b, ESSENCE),
+                Unlock::Reference { index: 0 },
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn tampered_signature() {
+        let a = key(1);
+        let mut tampered = unlock(&a, ESSENCE);
+        if let Unlock::Signature { signature, .. } = &mut tampered {
+            signature[0] ^= 0x01;
+        }
+        assert_eq!(
+            verify_unlocks(
+                ESSENCE,
+                &[address(&a), address(&a)],
+                &[unlock(&a, ESSENCE), tampered]
+            ),
+            Err(LedgerVerifyError::InvalidSignature(1))
+        );
+    }
+
+    #[test]
+    fn other_essence() {
+        let a = key(1);
+        assert_eq!(
+            verify_unlocks(ESSENCE, &[address(&a)], &[unlock(&a, b"other essence")]),
+            Err(LedgerVerifyError::InvalidSignature(0))
+        );
+    }
+
+    #[test]
+    fn wrong_address() {
+        assert_eq!(
+            verify_unlocks(ESSENCE, &[address(&key(2))], &[unlock(&key(1), ESSENCE)]),
+            Err(LedgerVerifyError::AddressMismatch(0))
+        );
+    }
+
+    #[test]
+    fn invalid_references() {
+        let (a, b) = (key(1), key(2));
+        // forward reference
+        assert_eq!(
+            verify_unlocks(
+                ESSENCE,
+                &[address(&a), address(&a)],
+                &[Unlock::Reference { index: 1 }, unlock(&a, ESSENCE)],
+            ),
+            Err(LedgerVerifyError::InvalidReference { input: 0, index: 1 })
+        );
+        // reference to another address
+        assert_eq!(
+            verify_unlocks(
+                ESSENCE,
+                &[address(&a), address(&b)],
+                &[unlock(&a, ESSENCE), Unlock::Reference { index: 0 }],
+            ),
+            Err(LedgerVerifyError::InvalidReference { input: 1, index: 0 })
+        );
+    }
+
+    #[test]
+    fn count_mismatch() {
+        assert_eq!(
+            verify_unlocks(ESSENCE, &[address(&key(1))], &[]),
+            Err(LedgerVerifyError::CountMismatch {
+                inputs: 1,
+                unlocks: 0
+            })
+        );
+    }
+}